@@ -0,0 +1,261 @@
+// Compares the id and bitset based puzzle state against the hash map based state it replaced, on
+// a generated puzzle big enough that a frame's worth of work has to fit in 16ms.
+//
+//     cargo bench --bench state
+//
+// The old state is reproduced here in just enough detail to do the same work: connecting every
+// edge of the puzzle, and building each frame's graphics data the way its get_dynamic_graphics_data
+// did. New frames go through the same get_dynamic_graphics_data call the game makes every frame.
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+use vertex::{EdgeMode, PuzzleData, PuzzleState, Rules};
+
+const GRID_SIZE: u32 = 158; // squares per side, two triangles each for just under 50k triangles
+const FRAMES: u32 = 20;
+
+// A square grid where every square is split into two triangles
+fn grid_puzzle() -> (String, Vec<(f32, f32)>, Vec<[u32; 3]>) {
+    let side = GRID_SIZE + 1;
+    let mut text = String::new();
+    let mut vertices = vec![];
+    for y in 0..side {
+        for x in 0..side {
+            text.push_str(&format!("{} {}\n", x, y));
+            vertices.push((x as f32, y as f32));
+        }
+    }
+    text.push_str("255 0 0\n0 0 255\n");
+
+    let mut triangles = vec![];
+    for y in 0..GRID_SIZE {
+        for x in 0..GRID_SIZE {
+            let corner = y * side + x;
+            triangles.push([corner, corner + 1, corner + side + 1]);
+            triangles.push([corner, corner + side + 1, corner + side]);
+        }
+    }
+    for (idx, t) in triangles.iter().enumerate() {
+        text.push_str(&format!("{} {} {} {}\n", t[0], t[1], t[2], idx % 2));
+    }
+    (text, vertices, triangles)
+}
+
+fn sorted_edges(triangle: &[u32; 3]) -> [(u32, u32); 3] {
+    let mut sorted = *triangle;
+    sorted.sort();
+    [(sorted[0], sorted[1]), (sorted[1], sorted[2]), (sorted[0], sorted[2])]
+}
+
+struct LegacyPuzzle {
+    vertices: Vec<(f32, f32)>,
+    edge_to_triangles: HashMap<(u32, u32), Vec<usize>>,
+    triangle_to_edges: HashMap<u32, [(u32, u32); 3]>,
+    vertices_to_edges: HashMap<u32, HashSet<(u32, u32)>>,
+}
+
+#[derive(Default)]
+struct LegacyState {
+    triangle_reqs: Vec<u32>,
+    unlocked_triangles: HashSet<usize>,
+    connected_edges: HashSet<(u32, u32)>,
+    connected_edges_by_vertex: HashMap<u32, HashSet<(u32, u32)>>,
+    permanent_edges_by_vertex: HashMap<u32, HashSet<(u32, u32)>>,
+    permanent_edges: HashSet<(u32, u32)>,
+    permanent_vertices: HashSet<u32>,
+}
+
+impl LegacyPuzzle {
+    fn new(vertices: Vec<(f32, f32)>, triangles: &[[u32; 3]]) -> LegacyPuzzle {
+        let mut out = LegacyPuzzle {
+            vertices,
+            edge_to_triangles: HashMap::new(),
+            triangle_to_edges: HashMap::new(),
+            vertices_to_edges: HashMap::new(),
+        };
+        for (idx, triangle) in triangles.iter().enumerate() {
+            let edges = sorted_edges(triangle);
+            for edge in &edges { out.edge_to_triangles.entry(*edge).or_default().push(idx); }
+            out.triangle_to_edges.insert(idx as u32, edges);
+        }
+        for edge in out.edge_to_triangles.keys() {
+            out.vertices_to_edges.entry(edge.0).or_default().insert(*edge);
+            out.vertices_to_edges.entry(edge.1).or_default().insert(*edge);
+        }
+        out
+    }
+
+    fn num_edges_from_vertex(&self, v: u32) -> usize { self.vertices_to_edges.get(&v).map(|e| e.len()).unwrap_or(0) }
+
+    fn get_vertex_near(&self, state: &LegacyState, point: (f32, f32), threshold: f32) -> Option<u32> {
+        for (idx, vertex) in self.vertices.iter().enumerate() {
+            if (vertex.0 - point.0).hypot(vertex.1 - point.1) <= threshold
+            && state.should_be_interactable(self, idx as u32) {
+                return Some(idx as u32)
+            }
+        }
+        None
+    }
+}
+
+// What the old get_dynamic_graphics_data built, with 32 bit indices since 16 bit ones overflow on
+// a puzzle this big
+#[derive(Default)]
+struct LegacyFrame {
+    triangle_indices: Vec<u32>,
+    line_vertices: Vec<f32>,
+    point_positions: Vec<f32>,
+    point_uvs: Vec<f32>,
+    point_textures: Vec<f32>,
+    point_indices: Vec<u32>,
+}
+
+impl LegacyState {
+    fn new(num_triangles: usize) -> LegacyState {
+        LegacyState { triangle_reqs: vec![3; num_triangles], ..Default::default() }
+    }
+
+    fn add_edge(&mut self, puzzle: &LegacyPuzzle, edge: (u32, u32)) {
+        if !self.connected_edges.insert(edge) { return }
+        self.connected_edges_by_vertex.entry(edge.0).or_default().insert(edge);
+        self.connected_edges_by_vertex.entry(edge.1).or_default().insert(edge);
+
+        for &triangle in &puzzle.edge_to_triangles[&edge] {
+            self.triangle_reqs[triangle] -= 1;
+            if self.triangle_reqs[triangle] != 0 { continue }
+            self.unlocked_triangles.insert(triangle);
+            for e_perm in puzzle.triangle_to_edges[&(triangle as u32)].iter() {
+                self.permanent_edges_by_vertex.entry(e_perm.0).or_default().insert(*e_perm);
+                self.permanent_edges_by_vertex.entry(e_perm.1).or_default().insert(*e_perm);
+                self.permanent_edges.insert(*e_perm);
+                for &v in &[e_perm.0, e_perm.1] {
+                    if self.permanent_edges_by_vertex[&v].len() == puzzle.vertices_to_edges[&v].len() {
+                        self.permanent_vertices.insert(v);
+                    }
+                }
+            }
+        }
+    }
+
+    fn permanent_edges_for_vertex(&self, v: u32) -> usize { self.permanent_edges_by_vertex.get(&v).map(|e| e.len()).unwrap_or(0) }
+
+    fn non_permanent_edges_for_vertex(&self, v: u32) -> usize {
+        self.connected_edges_by_vertex.get(&v).map(|e| e.len()).unwrap_or(0) - self.permanent_edges_for_vertex(v)
+    }
+
+    fn should_be_interactable(&self, puzzle: &LegacyPuzzle, v: u32) -> bool {
+        puzzle.num_edges_from_vertex(v) > self.permanent_edges_for_vertex(v) || self.non_permanent_edges_for_vertex(v) > 0
+    }
+
+    fn frame(&self, puzzle: &LegacyPuzzle, last_vertex: &Option<u32>, curr_pointer: &Option<(f32, f32)>) -> LegacyFrame {
+        let mut out = LegacyFrame::default();
+
+        let mut selected_vertices = HashSet::new();
+        let curr_pointer_vert = curr_pointer.and_then(|p| puzzle.get_vertex_near(self, p, 0.12));
+        if let Some(v) = last_vertex { selected_vertices.insert(*v); }
+        if let Some(v) = curr_pointer_vert { selected_vertices.insert(v); }
+
+        for &(start, end) in &self.connected_edges {
+            let ((start_x, start_y), (end_x, end_y)) = (puzzle.vertices[start as usize], puzzle.vertices[end as usize]);
+            out.line_vertices.append(&mut vec![start_x, start_y, end_x, end_y]);
+        }
+
+        for &idx in &self.unlocked_triangles {
+            let base = idx as u32 * 3;
+            out.triangle_indices.append(&mut vec![base, base + 1, base + 2]);
+        }
+
+        if let (Some(v), Some((x2, y2))) = (last_vertex, curr_pointer) {
+            let (x1, y1) = puzzle.vertices[*v as usize];
+            out.line_vertices.append(&mut vec![x1, y1, *x2, *y2]);
+        }
+
+        let mut offset = 0;
+        for (idx, &(x, y)) in puzzle.vertices.iter().enumerate() {
+            let remaining = puzzle.num_edges_from_vertex(idx as u32) - self.permanent_edges_for_vertex(idx as u32);
+            let non_permanent = self.non_permanent_edges_for_vertex(idx as u32);
+            if remaining == 0 && non_permanent == 0 { continue }
+
+            let multiplier = if selected_vertices.contains(&(idx as u32)) { 1.5 } else { 1.0 };
+            let half_width = multiplier * (0.07 + remaining as f32 * 0.015);
+            out.point_positions.append(&mut vec![
+                x - half_width, y - half_width, x + half_width, y - half_width,
+                x + half_width, y + half_width, x - half_width, y + half_width,
+            ]);
+            out.point_uvs.append(&mut vec![0.0, 1.0, 1.0, 1.0, 1.0, 0.0, 0.0, 0.0]);
+            out.point_textures.append(&mut vec![remaining as f32; 4]);
+            out.point_indices.append(&mut vec![offset, offset + 1, offset + 2, offset, offset + 2, offset + 3]);
+            offset += 4;
+        }
+
+        out
+    }
+}
+
+// Average time to build a frame's graphics data, old and new, with a vertex selected and the
+// pointer dragging a line like mid-move
+fn frames(puzzle: &LegacyPuzzle, legacy: &LegacyState, data: &PuzzleData, state: &PuzzleState) -> (Duration, Duration) {
+    let (last_vertex, pointer) = (Some(0), Some((0.5, 0.5)));
+    let (_, legacy_total) = time(|| {
+        (0..FRAMES).map(|_| legacy.frame(puzzle, &last_vertex, &pointer).line_vertices.len()).sum::<usize>()
+    });
+    let (_, total) = time(|| {
+        (0..FRAMES).map(|_| data.get_dynamic_graphics_data(state, &last_vertex, &pointer, &None).line_vertices.len()).sum::<usize>()
+    });
+    (legacy_total / FRAMES, total / FRAMES)
+}
+
+fn time<T, F: FnMut() -> T>(mut f: F) -> (T, Duration) {
+    let start = Instant::now();
+    let out = f();
+    (out, start.elapsed())
+}
+
+fn report(name: &str, legacy: Duration, current: Duration) {
+    println!(
+        "{:<28} old {:>9.2}ms   new {:>9.2}ms   {:>5.1}x",
+        name,
+        legacy.as_secs_f64() * 1000.0,
+        current.as_secs_f64() * 1000.0,
+        legacy.as_secs_f64() / current.as_secs_f64()
+    );
+}
+
+fn main() {
+    let (text, vertices, triangles) = grid_puzzle();
+    let (data, parse) = time(|| PuzzleData::parse(&text).unwrap());
+    println!("{} triangles, {} edges, {} vertices, parsed in {:.2}ms\n",
+        data.num_triangles(), data.num_edges(), data.num_vertices(), parse.as_secs_f64() * 1000.0);
+
+    let puzzle = LegacyPuzzle::new(vertices, &triangles);
+    let mut edges: Vec<(u32, u32)> = puzzle.edge_to_triangles.keys().cloned().collect();
+    edges.sort();
+
+    let (legacy, legacy_connect) = time(|| {
+        let mut state = LegacyState::new(triangles.len());
+        for &edge in &edges { state.add_edge(&puzzle, edge); }
+        state
+    });
+    let (state, connect) = time(|| {
+        let mut state = PuzzleState::from_data(&data, Rules { edge_mode: EdgeMode::Relaxed });
+        for edge in &edges { state.connect_edge(&data, edge); }
+        state
+    });
+    assert!(state.is_finished() && legacy.unlocked_triangles.len() == triangles.len());
+    report("connect every edge", legacy_connect, connect);
+
+    // Every vertex is drawn at the start, half way through most still are, and at the end none are
+    let mut legacy_half = LegacyState::new(triangles.len());
+    let mut half = PuzzleState::from_data(&data, Rules { edge_mode: EdgeMode::Relaxed });
+    for edge in edges.iter().step_by(2) {
+        legacy_half.add_edge(&puzzle, *edge);
+        half.connect_edge(&data, edge);
+    }
+    let empty = PuzzleState::from_data(&data, Rules { edge_mode: EdgeMode::Relaxed });
+    println!();
+    let (old, new) = frames(&puzzle, &LegacyState::new(triangles.len()), &data, &empty);
+    report("frame, nothing connected", old, new);
+    let (old, new) = frames(&puzzle, &legacy_half, &data, &half);
+    report("frame, half connected", old, new);
+    let (old, new) = frames(&puzzle, &legacy, &data, &state);
+    report("frame, finished", old, new);
+}
@@ -1,4 +1,4 @@
-import init, { run } from './pkg/vertex.js';
+import init, { run, EdgeMode } from './pkg/vertex.js';
 
 async function run_wasm() {
     await init();
@@ -7,7 +7,14 @@ async function run_wasm() {
     fetch(`/puzzles/${puzzle}.txt`)
         .then((res) => res.text())
         .then((text) => {
-            run(text);
+            // Kept on the window so testers can grab `vertexGame.move_log()` for bug reports
+            window.vertexGame = run(text, EdgeMode.Relaxed);
+            window.vertexGame.set_autosave(true);
+            try {
+                window.vertexGame.restore_saved();
+            } catch (e) {
+                console.warn(`Could not restore progress: ${e}`);
+            }
         });
 }
 
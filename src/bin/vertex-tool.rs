@@ -0,0 +1,65 @@
+use std::process;
+use vertex::{EdgeMode, PuzzleData, PuzzleState, Rules};
+
+// Command line tools for puzzle authors, mainly for checking puzzles in CI before publishing them.
+//
+//     vertex-tool solve <puzzle file>
+//
+// Prints a sequence of edges that finishes the puzzle, or why it can't be finished.
+// Exits with 1 if the puzzle can't be finished and 2 if it can't be read.
+
+fn usage() -> ! {
+    eprintln!("usage: vertex-tool solve <puzzle file>");
+    process::exit(2);
+}
+
+fn load(path: &str) -> PuzzleData {
+    let bytes = std::fs::read(path).unwrap_or_else(|e| {
+        eprintln!("could not read {}: {}", path, e);
+        process::exit(2);
+    });
+
+    let parsed = if bytes.starts_with(b"VXPZ") {
+        PuzzleData::from_binary(&bytes)
+    } else {
+        match String::from_utf8(bytes) {
+            Ok(text) => PuzzleData::parse(&text),
+            Err(_) => {
+                eprintln!("{} is neither a text nor a binary puzzle", path);
+                process::exit(2);
+            },
+        }
+    };
+    parsed.unwrap_or_else(|e| {
+        eprintln!("{}: {}", path, e);
+        process::exit(2);
+    })
+}
+
+fn solve(path: &str) {
+    let data = load(path);
+    let state = PuzzleState::from_data(&data, Rules { edge_mode: EdgeMode::Strict });
+    match vertex::solve(&data, &state) {
+        Ok(edges) => {
+            println!("{}: solved with {} edges", path, edges.len());
+            for (v0, v1) in edges {
+                println!("{} {}", v0, v1);
+            }
+        },
+        Err(obstacles) => {
+            eprintln!("{}: can't be finished", path);
+            for obstacle in obstacles {
+                eprintln!("  {}", obstacle);
+            }
+            process::exit(1);
+        },
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.iter().map(|a| a.as_str()).collect::<Vec<_>>().as_slice() {
+        ["solve", path] => solve(path),
+        _ => usage(),
+    }
+}
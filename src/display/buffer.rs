@@ -0,0 +1,82 @@
+use super::error::*;
+use std::rc::Rc;
+use wasm_bindgen::JsCast;
+use web_sys::{WebGlBuffer, WebGlRenderingContext as GL};
+use js_sys::WebAssembly;
+
+// A GL buffer that lives as long as the renderer does. Data that changes every frame is written
+// with sub-updates into storage that only ever grows, so a frame doesn't allocate anything on the GPU.
+pub struct GpuBuffer {
+    context: Rc<GL>,
+    buffer: WebGlBuffer,
+    target: u32, // ARRAY_BUFFER or ELEMENT_ARRAY_BUFFER
+    capacity: usize, // bytes
+}
+
+impl GpuBuffer {
+    pub fn new(context: &Rc<GL>, target: u32) -> Result<GpuBuffer, GraphicsError> {
+        let buffer = context.create_buffer().ok_or(GraphicsError::BufferError)?;
+        Ok(GpuBuffer { context: context.clone(), buffer, target, capacity: 0 })
+    }
+
+    pub fn bind(&self) {
+        self.context.bind_buffer(self.target, Some(&self.buffer));
+    }
+
+    // For data that's written once and drawn many times
+    pub fn upload_static_f32(&mut self, data: &[f32]) {
+        self.bind();
+        self.context.buffer_data_with_array_buffer_view(self.target, &f32_view(data), GL::STATIC_DRAW);
+        self.capacity = data.len() * 4;
+    }
+
+    pub fn upload_f32(&mut self, data: &[f32]) {
+        self.reserve(data.len() * 4);
+        self.context.buffer_sub_data_with_i32_and_array_buffer_view(self.target, 0, &f32_view(data));
+    }
+
+    pub fn upload_u16(&mut self, data: &[u16]) {
+        self.reserve(data.len() * 2);
+        self.context.buffer_sub_data_with_i32_and_array_buffer_view(self.target, 0, &u16_view(data));
+    }
+
+    pub fn upload_u32(&mut self, data: &[u32]) {
+        self.reserve(data.len() * 4);
+        self.context.buffer_sub_data_with_i32_and_array_buffer_view(self.target, 0, &u32_view(data));
+    }
+
+    // Binds the buffer, growing it to fit `bytes` if it's too small. Growing doubles the size so
+    // that a puzzle being filled in only reallocates a handful of times.
+    fn reserve(&mut self, bytes: usize) {
+        self.bind();
+        if bytes <= self.capacity { return }
+        self.capacity = bytes.next_power_of_two().max(256);
+        self.context.buffer_data_with_i32(self.target, self.capacity as i32, GL::DYNAMIC_DRAW);
+    }
+}
+
+impl Drop for GpuBuffer {
+    fn drop(&mut self) {
+        self.context.delete_buffer(Some(&self.buffer));
+    }
+}
+
+// Views straight into wasm memory, which are only valid until the next allocation might grow it
+fn f32_view(data: &[f32]) -> js_sys::Float32Array {
+    let location = data.as_ptr() as u32 / 4;
+    js_sys::Float32Array::new(&memory()).subarray(location, location + data.len() as u32)
+}
+
+fn u16_view(data: &[u16]) -> js_sys::Uint16Array {
+    let location = data.as_ptr() as u32 / 2;
+    js_sys::Uint16Array::new(&memory()).subarray(location, location + data.len() as u32)
+}
+
+fn u32_view(data: &[u32]) -> js_sys::Uint32Array {
+    let location = data.as_ptr() as u32 / 4;
+    js_sys::Uint32Array::new(&memory()).subarray(location, location + data.len() as u32)
+}
+
+fn memory() -> wasm_bindgen::JsValue {
+    wasm_bindgen::memory().dyn_into::<WebAssembly::Memory>().unwrap().buffer()
+}
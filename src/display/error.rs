@@ -5,5 +5,6 @@ quick_error! {
         ShaderError
         ProgramError
         DrawError
+        BufferError
     }
 }
\ No newline at end of file
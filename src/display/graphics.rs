@@ -1,11 +1,9 @@
-use super::{error::*, shader::*};
+use super::{buffer::*, error::*, shader::*, renderer::*};
 use super::super::geometry::{StaticGraphicsData, DynamicGraphicsData};
 use std::rc::Rc;
 use std::collections::HashMap;
 use wasm_bindgen::JsCast;
 use web_sys::WebGlRenderingContext as GL;
-use js_sys::WebAssembly;
-use nalgebra_glm::{TVec4, TMat4};
 
 static TRIANGLE_VS: &'static str = include_str!("./shaders/triangle-vertex.glsl");
 static TRIANGLE_FS: &'static str = include_str!("./shaders/triangle-fragment.glsl");
@@ -14,15 +12,43 @@ static LINE_FS: &'static str = include_str!("./shaders/line-fragment.glsl");
 static POINT_VS: &'static str = include_str!("./shaders/point-vertex.glsl");
 static POINT_FS: &'static str = include_str!("./shaders/point-fragment.glsl");
 
-const DEFAULT_CLEAR_COLOR: [f32; 4] = [0.8, 0.8, 0.8, 1.0];
+// Most vertices a single draw can reach with 16 bit indices
+const MAX_U16_VERTICES: u32 = 65535;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum BufferKind {
+    TrianglePositions, // static
+    TriangleColors, // static
+    TriangleIndices,
+    Lines,
+    WrongLines,
+    HintLines,
+    PointPositions,
+    PointUvs,
+    PointTextures,
+    PointIndices,
+}
+
+const BUFFER_KINDS: [(BufferKind, u32); 10] = [
+    (BufferKind::TrianglePositions, GL::ARRAY_BUFFER),
+    (BufferKind::TriangleColors, GL::ARRAY_BUFFER),
+    (BufferKind::TriangleIndices, GL::ELEMENT_ARRAY_BUFFER),
+    (BufferKind::Lines, GL::ARRAY_BUFFER),
+    (BufferKind::WrongLines, GL::ARRAY_BUFFER),
+    (BufferKind::HintLines, GL::ARRAY_BUFFER),
+    (BufferKind::PointPositions, GL::ARRAY_BUFFER),
+    (BufferKind::PointUvs, GL::ARRAY_BUFFER),
+    (BufferKind::PointTextures, GL::ARRAY_BUFFER),
+    (BufferKind::PointIndices, GL::ELEMENT_ARRAY_BUFFER),
+];
 
 pub struct Graphics {
     context: Rc<GL>,
     shaders: HashMap<ShaderKind, Shader>,
-    window_size: (u32, u32),
+    buffers: HashMap<BufferKind, GpuBuffer>,
+    view: View,
     clear_color: [f32; 4],
-    view_matrix: TMat4<f32>,
-    viewport: TVec4<f32>,
+    u32_indices: bool, // whether OES_element_index_uint is available
 }
 
 impl Graphics {
@@ -32,147 +58,135 @@ impl Graphics {
             .ok_or(GraphicsError::ContextFailed)?
             .dyn_into::<GL>().map_err(|_| GraphicsError::ContextFailed)?;
 
+        // WebGL1 only draws with 32 bit indices given this extension. Without it, big puzzles get split
+        // into draws that each fit in 16 bits.
+        let u32_indices = matches!(context.get_extension("OES_element_index_uint"), Ok(Some(_)));
+
         let mut ret = Graphics{
             context: Rc::new(context),
             shaders: HashMap::new(),
-            window_size: (canvas.width(), canvas.height()),
+            buffers: HashMap::new(),
+            view: View::new((canvas.width(), canvas.height())),
             clear_color: DEFAULT_CLEAR_COLOR,
-            view_matrix: nalgebra_glm::ortho(-3.0, 3.0, -3.0, 3.0, 0.1, 1000.0),
-            viewport: nalgebra_glm::make_vec4(&[0., 0., canvas.width() as f32, canvas.height() as f32]),
+            u32_indices,
         };
 
         ret.shaders.insert(ShaderKind::Triangles, Shader::new(&ret.context, TRIANGLE_VS, TRIANGLE_FS)?);
         ret.shaders.insert(ShaderKind::Lines, Shader::new(&ret.context, LINE_VS, LINE_FS)?);
         ret.shaders.insert(ShaderKind::Points, Shader::new(&ret.context, POINT_VS, POINT_FS)?);
+        for &(kind, target) in &BUFFER_KINDS {
+            ret.buffers.insert(kind, GpuBuffer::new(&ret.context, target)?);
+        }
         Ok(ret)
     }
 
-    // Take x, y pixels and map them to model space
-    pub fn unproject(&self, x: i32, y: i32) -> (f32, f32) {
-        let unprojected = nalgebra_glm::unproject(
-            // Need to invert the y since canvas +Y goes downwards
-            &nalgebra_glm::make_vec3(&[x as f32, (self.window_size.1 as i32 - y) as f32, 0.]),
-            &nalgebra_glm::identity(),
-            &self.view_matrix,
-            self.viewport
-        );
-        (unprojected.x, unprojected.y)
+    pub fn set_clear_color(&mut self, color: [f32; 4]) {
+        self.clear_color = color;
     }
 
-    pub fn set_bounds(&mut self, lower: (f32, f32), upper: (f32, f32)) {
-        self.view_matrix = nalgebra_glm::ortho(lower.0 - 1.0, upper.0 + 1.0, lower.1 - 1.0, upper.1 + 1.0, 0.1, 1000.0);
+    fn buffer(&mut self, kind: BufferKind) -> &mut GpuBuffer {
+        self.buffers.get_mut(&kind).unwrap()
     }
 
-    pub fn draw(&self, static_data: &StaticGraphicsData, dynamic_data: &DynamicGraphicsData) {
-        self.context.clear_color(self.clear_color[0], self.clear_color[1], self.clear_color[2], self.clear_color[3]);
-        self.context.clear(GL::COLOR_BUFFER_BIT | GL::DEPTH_BUFFER_BIT);
-        self.context.viewport(0, 0, self.window_size.0 as i32, self.window_size.1 as i32);
-
-        let mut view_matrix = [0.; 16];
-        view_matrix.clone_from_slice(self.view_matrix.as_slice());
-
-        self.draw_triangles(
-            &view_matrix,
-            &static_data.triangle_position_vertices,
-            &static_data.triangle_color_idx_vertices,
-            &dynamic_data.triangle_indices,
-            &static_data.colors_uniform,
-        );
-
-        self.draw_lines(
-            &view_matrix,
-            &dynamic_data.line_vertices
-        );
-
-        self.draw_points(
-            &view_matrix,
-            &dynamic_data.point_positions,
-            &dynamic_data.point_uvs,
-            &dynamic_data.point_textures,
-            &dynamic_data.point_indices,
-        );
+    // Points an attribute at a buffer. Attributes are disabled again after each draw so that a
+    // later draw with fewer attributes doesn't read past the end of a stale buffer.
+    // `first` is the vertex that index 0 refers to.
+    fn bind_attrib(&self, kind: BufferKind, attrib: u32, size: i32, first: u32) {
+        self.buffers[&kind].bind();
+        self.context.vertex_attrib_pointer_with_i32(attrib, size, GL::FLOAT, false, 0, first as i32 * size * 4);
+        self.context.enable_vertex_attrib_array(attrib);
     }
 
-    pub fn set_clear_color(&mut self, color: [f32; 4]) {
-        self.clear_color = color;
+    // Draws indexed triangles with each attribute given as (buffer, attribute location, floats per vertex)
+    fn draw_indexed(&mut self, index_kind: BufferKind, indices: &[u32], attribs: &[(BufferKind, u32, i32)]) {
+        if self.u32_indices {
+            self.buffer(index_kind).upload_u32(indices);
+            for &(kind, attrib, size) in attribs { self.bind_attrib(kind, attrib, size, 0); }
+            self.buffers[&index_kind].bind();
+            self.context.draw_elements_with_i32(GL::TRIANGLES, indices.len() as i32, GL::UNSIGNED_INT, 0);
+        } else {
+            let chunks = U16Chunks::from_indices(indices);
+            self.buffer(index_kind).upload_u16(&chunks.indices);
+            for &(first, start, count) in &chunks.draws {
+                for &(kind, attrib, size) in attribs { self.bind_attrib(kind, attrib, size, first); }
+                self.buffers[&index_kind].bind();
+                self.context.draw_elements_with_i32(GL::TRIANGLES, count as i32, GL::UNSIGNED_SHORT, start as i32 * 2);
+            }
+        }
+        for &(_, attrib, _) in attribs { self.context.disable_vertex_attrib_array(attrib); }
     }
 
-    fn draw_triangles(
-        &self,
-        view_matrix: &[f32; 16],
-        vertex_positions: &Vec<f32>,
-        vertex_colors: &Vec<f32>,
-        indices: &Vec<u16>,
-        colors: &Vec<f32>,
-    ) {
-        if indices.len() == 0 { return }
+    fn draw_triangles(&mut self, view_matrix: &[f32; 16], indices: &[u32]) {
+        if indices.is_empty() { return }
 
         let shader = self.shaders.get(&ShaderKind::Triangles).unwrap();
         self.context.use_program(Some(&shader.program));
 
-        // Set up and buffer position/color index attributes
         let pos_attrib = self.context.get_attrib_location(&shader.program, "position") as u32;
         let color_attrib = self.context.get_attrib_location(&shader.program, "color") as u32;
-        self.buffer_f32_data(vertex_positions, pos_attrib, 2);
-        self.buffer_f32_data(vertex_colors, color_attrib, 1);
-        self.buffer_u16_indices(indices);
-
-        // Set color and view matrix uniforms
-        let colors_uniform = shader.get_uniform_location(&self.context, "colors");
-        self.context.uniform3fv_with_f32_array(colors_uniform.as_ref(), colors);
 
         let view_matrix_uniform = shader.get_uniform_location(&self.context, "viewMatrix");
         self.context.uniform_matrix4fv_with_f32_array(view_matrix_uniform.as_ref(), false, view_matrix);
 
-        // Draw triangles
-        self.context.draw_elements_with_i32(GL::TRIANGLES, indices.len() as i32, GL::UNSIGNED_SHORT, 0);
+        // Draw triangles, blending since colors may be translucent. Positions and colors were uploaded
+        // with the static data, only the indices change.
+        self.context.enable(GL::BLEND);
+        self.context.blend_func(GL::SRC_ALPHA, GL::ONE_MINUS_SRC_ALPHA);
+        self.draw_indexed(BufferKind::TriangleIndices, indices, &[
+            (BufferKind::TrianglePositions, pos_attrib, 2),
+            (BufferKind::TriangleColors, color_attrib, 4),
+        ]);
+        self.context.disable(GL::BLEND);
     }
 
     fn draw_lines(
-        &self, 
+        &mut self,
         view_matrix: &[f32; 16],
-        vertices: &Vec<f32>
+        kind: BufferKind,
+        vertices: &[f32],
+        color: &[f32; 4],
+        width: f32,
     ) {
-        if vertices.len() == 0 { return }
+        if vertices.is_empty() { return }
+
+        self.buffer(kind).upload_f32(vertices);
 
         let shader = self.shaders.get(&ShaderKind::Lines).unwrap();
         self.context.use_program(Some(&shader.program));
 
-        // Set up and buffer position attribute
         let pos_attrib = self.context.get_attrib_location(&shader.program, "position") as u32;
-        self.buffer_f32_data(vertices, pos_attrib, 2);
+        self.bind_attrib(kind, pos_attrib, 2, 0);
+
+        // Set color and view matrix uniforms
+        let color_uniform = shader.get_uniform_location(&self.context, "color");
+        self.context.uniform4fv_with_f32_array(color_uniform.as_ref(), color);
 
-        // Set view matrix uniform
         let view_matrix_uniform = shader.get_uniform_location(&self.context, "viewMatrix");
         self.context.uniform_matrix4fv_with_f32_array(view_matrix_uniform.as_ref(), false, view_matrix);
 
-        // Draw disconnected lines
-        self.context.line_width(2.0);
+        // Draw disconnected lines, blending since the color may be translucent
+        self.context.enable(GL::BLEND);
+        self.context.blend_func(GL::SRC_ALPHA, GL::ONE_MINUS_SRC_ALPHA);
+        self.context.line_width(width * self.view.pixel_ratio());
         self.context.draw_arrays(GL::LINES, 0, (vertices.len() >> 1) as i32);
+        self.context.disable(GL::BLEND);
+        self.context.disable_vertex_attrib_array(pos_attrib);
     }
 
-    fn draw_points(
-        &self, 
-        view_matrix: &[f32; 16],
-        positions: &Vec<f32>,
-        uvs: &Vec<f32>,
-        textures: &Vec<f32>,
-        indices: &Vec<u16>,
-    ){
-        if indices.len() == 0 { return }
+    fn draw_points(&mut self, view_matrix: &[f32; 16], instances: &[f32]) {
+        if instances.is_empty() { return }
+
+        let quads = PointQuads::from_instances(instances);
+        self.buffer(BufferKind::PointPositions).upload_f32(&quads.positions);
+        self.buffer(BufferKind::PointUvs).upload_f32(&quads.uvs);
+        self.buffer(BufferKind::PointTextures).upload_f32(&quads.textures);
 
         let shader = self.shaders.get(&ShaderKind::Points).unwrap();
         self.context.use_program(Some(&shader.program));
 
-        // Set up and buffer position/index attributes
         let pos_attrib = self.context.get_attrib_location(&shader.program, "position") as u32;
         let uv_attrib = self.context.get_attrib_location(&shader.program, "uv") as u32;
         let texture_attrib = self.context.get_attrib_location(&shader.program, "texture_index") as u32;
-        self.buffer_f32_data(positions, pos_attrib, 2);
-        self.buffer_f32_data(uvs, uv_attrib, 2);
-        self.buffer_f32_data(textures, texture_attrib, 1);
-        self.buffer_u16_indices(indices);
-
         // Set view matrix uniform
         let view_matrix_uniform = shader.get_uniform_location(&self.context, "viewMatrix");
         self.context.uniform_matrix4fv_with_f32_array(view_matrix_uniform.as_ref(), false, view_matrix);
@@ -180,45 +194,125 @@ impl Graphics {
         // Draw point quads
         self.context.enable(GL::BLEND);
         self.context.blend_func(GL::SRC_ALPHA, GL::ONE_MINUS_SRC_ALPHA);
-        self.context.draw_elements_with_i32(GL::TRIANGLES, indices.len() as i32, GL::UNSIGNED_SHORT, 0);
+        self.draw_indexed(BufferKind::PointIndices, &quads.indices, &[
+            (BufferKind::PointPositions, pos_attrib, 2),
+            (BufferKind::PointUvs, uv_attrib, 2),
+            (BufferKind::PointTextures, texture_attrib, 1),
+        ]);
         self.context.disable(GL::BLEND);
     }
+}
+
+impl Renderer for Graphics {
+    fn set_bounds(&mut self, lower: (f32, f32), upper: (f32, f32)) { self.view.set_bounds(lower, upper); }
+    fn resize(&mut self, width: u32, height: u32, pixel_ratio: f32) { self.view.resize(width, height, pixel_ratio); }
+    fn unproject(&self, x: i32, y: i32) -> (f32, f32) { self.view.unproject(x, y) }
+
+    // Triangle positions and colors never change, so they go to the GPU once
+    fn set_static_data(&mut self, static_data: &StaticGraphicsData) {
+        self.buffer(BufferKind::TrianglePositions).upload_static_f32(&static_data.triangle_position_vertices);
+        self.buffer(BufferKind::TriangleColors).upload_static_f32(&static_data.triangle_color_vertices);
+    }
+
+    fn draw(&mut self, dynamic_data: &DynamicGraphicsData, time: f64) {
+        self.context.clear_color(self.clear_color[0], self.clear_color[1], self.clear_color[2], self.clear_color[3]);
+        self.context.clear(GL::COLOR_BUFFER_BIT | GL::DEPTH_BUFFER_BIT);
+        self.context.viewport(0, 0, self.view.window_size.0 as i32, self.view.window_size.1 as i32);
+        let view_matrix = self.view.matrix_array();
+
+        self.draw_triangles(&view_matrix, &dynamic_data.triangle_indices);
+        self.draw_lines(&view_matrix, BufferKind::Lines, &dynamic_data.line_vertices, &LINE_COLOR, LINE_WIDTH);
+        self.draw_lines(
+            &view_matrix,
+            BufferKind::WrongLines,
+            &dynamic_data.wrong_line_vertices,
+            &WRONG_LINE_COLOR,
+            LINE_WIDTH,
+        );
+        self.draw_lines(
+            &view_matrix,
+            BufferKind::HintLines,
+            &dynamic_data.hint_line_vertices,
+            &hint_line_color(dynamic_data, time),
+            HINT_LINE_WIDTH,
+        );
+        self.draw_points(&view_matrix, &dynamic_data.point_instances);
+    }
+}
 
-    fn buffer_f32_data(&self, data: &[f32], attrib: u32, size: i32) {
-        let memory_buffer = wasm_bindgen::memory()
-            .dyn_into::<WebAssembly::Memory>()
-            .unwrap()
-            .buffer();
+// Buffers delete themselves, the programs are all that's left
+impl Drop for Graphics {
+    fn drop(&mut self) {
+        self.buffers.clear();
+        for shader in self.shaders.values() { self.context.delete_program(Some(&shader.program)); }
+    }
+}
 
-        let data_location = data.as_ptr() as u32 / 4;
+// Triangle indices rewritten as 16 bit indices, in runs that each cover at most MAX_U16_VERTICES
+// vertices. Each draw is (first vertex, first index, index count) and its indices are relative to
+// its first vertex.
+struct U16Chunks {
+    indices: Vec<u16>,
+    draws: Vec<(u32, usize, usize)>,
+}
 
-        let data_array = js_sys::Float32Array::new(&memory_buffer)
-            .subarray(data_location, data_location + data.len() as u32);
+impl U16Chunks {
+    fn from_indices(indices: &[u32]) -> U16Chunks {
+        let mut out = U16Chunks { indices: vec![], draws: vec![] };
+        let mut first = 0;
+        for triangle in indices.chunks(3) {
+            let min = *triangle.iter().min().unwrap();
+            let max = *triangle.iter().max().unwrap();
+            // Triangles come in vertex order, so start a new draw whenever one doesn't fit the current
+            let fits = min >= first && max - first < MAX_U16_VERTICES;
+            if out.draws.is_empty() || !fits {
+                first = min;
+                out.draws.push((first, out.indices.len(), 0));
+            }
+            out.indices.extend(triangle.iter().map(|&idx| (idx - first) as u16));
+            out.draws.last_mut().unwrap().2 += triangle.len();
+        }
+        out
+    }
+}
 
-        let buffer = self.context.create_buffer().unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        self.context.bind_buffer(GL::ARRAY_BUFFER, Some(&buffer));
-        self.context.buffer_data_with_array_buffer_view(GL::ARRAY_BUFFER, &data_array, GL::STATIC_DRAW);
-        self.context.vertex_attrib_pointer_with_i32(attrib, size, GL::FLOAT, false, 0, 0);
-        self.context.enable_vertex_attrib_array(attrib);
+    // Every draw's indices, back in terms of the whole vertex buffer
+    fn absolute(chunks: &U16Chunks) -> Vec<u32> {
+        chunks.draws.iter()
+            .flat_map(|&(first, start, count)| chunks.indices[start..start + count].iter().map(move |&idx| first + idx as u32))
+            .collect()
     }
 
-    fn buffer_u16_indices(&self, indices: &[u16]) {
-        let memory_buffer = wasm_bindgen::memory()
-            .dyn_into::<WebAssembly::Memory>()
-            .unwrap()
-            .buffer();
+    #[test]
+    fn small_puzzles_draw_at_once() {
+        let indices: Vec<u32> = (0..300).collect();
+        let chunks = U16Chunks::from_indices(&indices);
+        assert_eq!(chunks.draws, vec![(0, 0, 300)]);
+        assert_eq!(absolute(&chunks), indices);
+    }
 
-        let indices_location = indices.as_ptr() as u32 / 2;
-        let indices_array = js_sys::Uint16Array::new(&memory_buffer)
-            .subarray(indices_location, indices_location + indices.len() as u32);
+    #[test]
+    fn large_puzzles_are_split_into_16_bit_draws() {
+        // Every other triangle of a puzzle with 60000 of them
+        let indices: Vec<u32> = (0..60000u32).step_by(2).flat_map(|t| vec![t * 3, t * 3 + 1, t * 3 + 2]).collect();
+        let chunks = U16Chunks::from_indices(&indices);
+        assert!(chunks.draws.len() > 1);
+        for &(_, start, count) in &chunks.draws {
+            assert!(chunks.indices[start..start + count].iter().all(|&idx| (idx as u32) < MAX_U16_VERTICES));
+            assert_eq!(count % 3, 0);
+        }
+        assert_eq!(absolute(&chunks), indices);
+    }
 
-        let index_buffer = self.context.create_buffer().unwrap();
-        self.context.bind_buffer(GL::ELEMENT_ARRAY_BUFFER, Some(&index_buffer));
-        self.context.buffer_data_with_array_buffer_view(
-            GL::ELEMENT_ARRAY_BUFFER,
-            &indices_array,
-            GL::STATIC_DRAW,
-        );
+    #[test]
+    fn triangles_out_of_order_start_new_draws() {
+        let indices = vec![70000, 70001, 70002, 3, 4, 5, 70003, 70004, 70005];
+        let chunks = U16Chunks::from_indices(&indices);
+        assert_eq!(chunks.draws, vec![(70000, 0, 3), (3, 3, 3), (70003, 6, 3)]);
+        assert_eq!(absolute(&chunks), indices);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,200 @@
+use super::{buffer::*, error::*, shader::*, renderer::*};
+use super::super::geometry::{StaticGraphicsData, DynamicGraphicsData};
+use std::rc::Rc;
+use std::collections::HashMap;
+use wasm_bindgen::JsCast;
+use web_sys::{WebGl2RenderingContext as GL2, WebGlRenderingContext as GL, WebGlVertexArrayObject};
+
+static TRIANGLE_VS: &str = include_str!("./shaders/triangle-vertex.glsl");
+static TRIANGLE_FS: &str = include_str!("./shaders/triangle-fragment.glsl");
+static LINE_VS: &str = include_str!("./shaders/line-vertex.glsl");
+static LINE_FS: &str = include_str!("./shaders/line-fragment.glsl");
+static POINT_VS: &str = include_str!("./shaders/point-instanced-vertex.glsl");
+static POINT_FS: &str = include_str!("./shaders/point-fragment.glsl");
+
+// Drawn as a triangle strip, once for every point
+const POINT_CORNERS: [f32; 8] = [-1.0, -1.0, 1.0, -1.0, -1.0, 1.0, 1.0, 1.0];
+
+// A vertex array object along with the buffers its attributes read from, in the order they were added
+struct VertexArray {
+    context: Rc<GL2>,
+    vao: WebGlVertexArrayObject,
+    buffers: Vec<GpuBuffer>,
+}
+
+impl VertexArray {
+    fn new(context: &Rc<GL2>) -> Result<VertexArray, GraphicsError> {
+        let vao = context.create_vertex_array().ok_or(GraphicsError::BufferError)?;
+        Ok(VertexArray { context: context.clone(), vao, buffers: vec![] })
+    }
+
+    // Element array bindings belong to the bound vertex array, so this has to come before any
+    // index upload or the indices end up attached to whichever array was bound last
+    fn bind(&self) { self.context.bind_vertex_array(Some(&self.vao)); }
+    fn unbind(&self) { self.context.bind_vertex_array(None); }
+
+    // Adds a buffer that feeds `size` floats to the attribute for every vertex, or for every
+    // instance when `per_instance` is set
+    fn add_attrib(&mut self, gl: &Rc<GL>, attrib: i32, size: i32, per_instance: bool) -> Result<(), GraphicsError> {
+        if attrib < 0 { return Err(GraphicsError::ProgramError) }
+        let buffer = GpuBuffer::new(gl, GL::ARRAY_BUFFER)?;
+        self.bind();
+        buffer.bind();
+        self.context.vertex_attrib_pointer_with_i32(attrib as u32, size, GL::FLOAT, false, 0, 0);
+        self.context.enable_vertex_attrib_array(attrib as u32);
+        self.context.vertex_attrib_divisor(attrib as u32, if per_instance { 1 } else { 0 });
+        self.unbind();
+        self.buffers.push(buffer);
+        Ok(())
+    }
+
+    fn add_indices(&mut self, gl: &Rc<GL>) -> Result<(), GraphicsError> {
+        let buffer = GpuBuffer::new(gl, GL::ELEMENT_ARRAY_BUFFER)?;
+        self.bind();
+        buffer.bind();
+        self.unbind();
+        self.buffers.push(buffer);
+        Ok(())
+    }
+}
+
+impl Drop for VertexArray {
+    fn drop(&mut self) {
+        self.context.delete_vertex_array(Some(&self.vao));
+    }
+}
+
+// Draws with WebGL2, where attribute layouts live in vertex array objects set up once and every
+// vertex circle is an instance of the same square instead of four vertices built on the CPU
+pub struct Graphics2 {
+    context: Rc<GL2>,
+    // WebGL2 contexts have every WebGL1 method, so shaders and buffers shared with `Graphics` get
+    // the same context typed as WebGL1
+    gl: Rc<GL>,
+    shaders: HashMap<ShaderKind, Shader>,
+    triangles: VertexArray, // positions, colors, indices
+    lines: [VertexArray; 3], // connected, wrong and hint lines
+    points: VertexArray, // corners, instances
+    view: View,
+    clear_color: [f32; 4],
+}
+
+impl Graphics2 {
+    pub fn from_canvas(canvas: &web_sys::HtmlCanvasElement) -> Result<Graphics2, GraphicsError> {
+        let context = canvas.get_context("webgl2")
+            .map_err(|_| GraphicsError::ContextFailed)?
+            .ok_or(GraphicsError::ContextFailed)?
+            .dyn_into::<GL2>().map_err(|_| GraphicsError::ContextFailed)?;
+        let gl = Rc::new(context.clone().unchecked_into::<GL>());
+        let context = Rc::new(context);
+
+        let mut shaders = HashMap::new();
+        shaders.insert(ShaderKind::Triangles, Shader::new(&gl, TRIANGLE_VS, TRIANGLE_FS)?);
+        shaders.insert(ShaderKind::Lines, Shader::new(&gl, LINE_VS, LINE_FS)?);
+        shaders.insert(ShaderKind::Points, Shader::new(&gl, POINT_VS, POINT_FS)?);
+        let attrib = |kind: ShaderKind, name: &str| gl.get_attrib_location(&shaders[&kind].program, name);
+
+        let mut triangles = VertexArray::new(&context)?;
+        triangles.add_attrib(&gl, attrib(ShaderKind::Triangles, "position"), 2, false)?;
+        triangles.add_attrib(&gl, attrib(ShaderKind::Triangles, "color"), 4, false)?;
+        triangles.add_indices(&gl)?;
+
+        let mut lines = [VertexArray::new(&context)?, VertexArray::new(&context)?, VertexArray::new(&context)?];
+        for vao in lines.iter_mut() {
+            vao.add_attrib(&gl, attrib(ShaderKind::Lines, "position"), 2, false)?;
+        }
+
+        let mut points = VertexArray::new(&context)?;
+        points.add_attrib(&gl, attrib(ShaderKind::Points, "corner"), 2, false)?;
+        points.add_attrib(&gl, attrib(ShaderKind::Points, "instance"), 4, true)?;
+        points.buffers[0].upload_static_f32(&POINT_CORNERS);
+
+        Ok(Graphics2 {
+            context,
+            gl,
+            shaders,
+            triangles,
+            lines,
+            points,
+            view: View::new((canvas.width(), canvas.height())),
+            clear_color: DEFAULT_CLEAR_COLOR,
+        })
+    }
+
+    fn use_shader(&self, kind: ShaderKind, view_matrix: &[f32; 16]) -> &Shader {
+        let shader = &self.shaders[&kind];
+        self.context.use_program(Some(&shader.program));
+        let view_matrix_uniform = shader.get_uniform_location(&self.gl, "viewMatrix");
+        self.context.uniform_matrix4fv_with_f32_array(view_matrix_uniform.as_ref(), false, view_matrix);
+        shader
+    }
+
+    fn draw_triangles(&mut self, view_matrix: &[f32; 16], indices: &[u32]) {
+        if indices.is_empty() { return }
+
+        self.triangles.bind();
+        self.triangles.buffers[2].upload_u32(indices);
+        self.use_shader(ShaderKind::Triangles, view_matrix);
+        self.context.draw_elements_with_i32(GL::TRIANGLES, indices.len() as i32, GL::UNSIGNED_INT, 0);
+        self.triangles.unbind();
+    }
+
+    fn draw_lines(&mut self, view_matrix: &[f32; 16], which: usize, vertices: &[f32], color: &[f32; 4], width: f32) {
+        if vertices.is_empty() { return }
+
+        self.lines[which].bind();
+        self.lines[which].buffers[0].upload_f32(vertices);
+        let shader = self.use_shader(ShaderKind::Lines, view_matrix);
+        let color_uniform = shader.get_uniform_location(&self.gl, "color");
+        self.context.uniform4fv_with_f32_array(color_uniform.as_ref(), color);
+        self.context.line_width(width * self.view.pixel_ratio());
+        self.context.draw_arrays(GL::LINES, 0, (vertices.len() >> 1) as i32);
+        self.lines[which].unbind();
+    }
+
+    fn draw_points(&mut self, view_matrix: &[f32; 16], instances: &[f32]) {
+        if instances.is_empty() { return }
+
+        self.points.bind();
+        self.points.buffers[1].upload_f32(instances);
+        self.use_shader(ShaderKind::Points, view_matrix);
+        self.context.draw_arrays_instanced(GL::TRIANGLE_STRIP, 0, 4, (instances.len() / 4) as i32);
+        self.points.unbind();
+    }
+}
+
+impl Renderer for Graphics2 {
+    fn set_bounds(&mut self, lower: (f32, f32), upper: (f32, f32)) { self.view.set_bounds(lower, upper); }
+    fn resize(&mut self, width: u32, height: u32, pixel_ratio: f32) { self.view.resize(width, height, pixel_ratio); }
+    fn unproject(&self, x: i32, y: i32) -> (f32, f32) { self.view.unproject(x, y) }
+
+    fn set_static_data(&mut self, static_data: &StaticGraphicsData) {
+        self.triangles.buffers[0].upload_static_f32(&static_data.triangle_position_vertices);
+        self.triangles.buffers[1].upload_static_f32(&static_data.triangle_color_vertices);
+    }
+
+    fn draw(&mut self, dynamic_data: &DynamicGraphicsData, time: f64) {
+        self.context.clear_color(self.clear_color[0], self.clear_color[1], self.clear_color[2], self.clear_color[3]);
+        self.context.clear(GL::COLOR_BUFFER_BIT | GL::DEPTH_BUFFER_BIT);
+        self.context.viewport(0, 0, self.view.window_size.0 as i32, self.view.window_size.1 as i32);
+        let view_matrix = self.view.matrix_array();
+
+        // Everything may be translucent
+        self.context.enable(GL::BLEND);
+        self.context.blend_func(GL::SRC_ALPHA, GL::ONE_MINUS_SRC_ALPHA);
+        self.draw_triangles(&view_matrix, &dynamic_data.triangle_indices);
+        self.draw_lines(&view_matrix, 0, &dynamic_data.line_vertices, &LINE_COLOR, LINE_WIDTH);
+        self.draw_lines(&view_matrix, 1, &dynamic_data.wrong_line_vertices, &WRONG_LINE_COLOR, LINE_WIDTH);
+        let hint_color = hint_line_color(dynamic_data, time);
+        self.draw_lines(&view_matrix, 2, &dynamic_data.hint_line_vertices, &hint_color, HINT_LINE_WIDTH);
+        self.draw_points(&view_matrix, &dynamic_data.point_instances);
+        self.context.disable(GL::BLEND);
+    }
+}
+
+// Vertex arrays and buffers delete themselves, the programs are all that's left
+impl Drop for Graphics2 {
+    fn drop(&mut self) {
+        for shader in self.shaders.values() { self.context.delete_program(Some(&shader.program)); }
+    }
+}
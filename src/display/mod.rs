@@ -1,3 +1,19 @@
 pub mod graphics;
+pub mod graphics2;
+pub mod renderer;
+pub mod software;
 pub mod error;
-pub(crate) mod shader;
\ No newline at end of file
+pub(crate) mod shader;
+pub(crate) mod buffer;
+
+// WebGL2 where the browser supports it, falling back to WebGL1 where it doesn't
+pub fn renderer_for_canvas(canvas: &web_sys::HtmlCanvasElement) -> Result<Box<dyn renderer::Renderer>, error::GraphicsError> {
+    match graphics2::Graphics2::from_canvas(canvas) {
+        Ok(renderer) => Ok(Box::new(renderer)),
+        Err(error::GraphicsError::ContextFailed) => {
+            web_sys::console::log_1(&"WebGL2 isn't available, drawing with WebGL1".into());
+            Ok(Box::new(graphics::Graphics::from_canvas(canvas)?))
+        },
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,158 @@
+use super::super::geometry::{StaticGraphicsData, DynamicGraphicsData};
+use nalgebra_glm::{TVec4, TMat4};
+
+pub const DEFAULT_CLEAR_COLOR: [f32; 4] = [0.8, 0.8, 0.8, 1.0];
+pub const LINE_COLOR: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+pub const WRONG_LINE_COLOR: [f32; 4] = [0.85, 0.1, 0.1, 0.8];
+pub const LINE_WIDTH: f32 = 2.0;
+pub const HINT_LINE_WIDTH: f32 = 3.0;
+const HINT_PULSE_PERIOD: f64 = 1200.0; // milliseconds
+const BOUNDS_PADDING: f32 = 0.2; // of the puzzle's larger side, on every side
+
+// Something that can draw a puzzle. `Graphics2` and `Graphics` draw with WebGL2 and WebGL1 in the browser and
+// `SoftwareRenderer` draws into memory so that rendering can be checked without either.
+pub trait Renderer {
+    // Frames the area between the bounds, with some padding, as large as fits in the output
+    fn set_bounds(&mut self, lower: (f32, f32), upper: (f32, f32));
+
+    // `width` and `height` are in device pixels, `pixel_ratio` is device pixels per CSS pixel
+    fn resize(&mut self, width: u32, height: u32, pixel_ratio: f32);
+
+    // Take x, y CSS pixels, like mouse event offsets, and map them to model space
+    fn unproject(&self, x: i32, y: i32) -> (f32, f32);
+
+    // The parts of a puzzle that don't change while it's played, needed before the first draw
+    fn set_static_data(&mut self, static_data: &StaticGraphicsData);
+
+    // `time` is in milliseconds and drives animations like the pulsing hint line
+    fn draw(&mut self, dynamic_data: &DynamicGraphicsData, time: f64);
+}
+
+// Model space area shown for the given puzzle bounds, as (left, right, bottom, top). The padding
+// scales with the puzzle so it looks the same whatever units the puzzle was made in.
+pub fn padded_bounds(lower: (f32, f32), upper: (f32, f32)) -> (f32, f32, f32, f32) {
+    let extent = (upper.0 - lower.0).max(upper.1 - lower.1);
+    let padding = if extent > 0.0 { extent * BOUNDS_PADDING } else { 1.0 };
+    (lower.0 - padding, upper.0 + padding, lower.1 - padding, upper.1 + padding)
+}
+
+// Grows the shorter side of the bounds so that model space is scaled the same on both axes, which
+// leaves bars of clear color on two sides when the output doesn't have the puzzle's aspect ratio
+pub fn letterboxed(bounds: (f32, f32, f32, f32), size: (u32, u32)) -> (f32, f32, f32, f32) {
+    let (left, right, bottom, top) = bounds;
+    let (width, height) = (size.0.max(1) as f32, size.1.max(1) as f32);
+    let scale = ((right - left) / width).max((top - bottom) / height); // model units per pixel
+    let (center_x, center_y) = ((left + right) / 2.0, (bottom + top) / 2.0);
+    let (half_width, half_height) = (scale * width / 2.0, scale * height / 2.0);
+    (center_x - half_width, center_x + half_width, center_y - half_height, center_y + half_height)
+}
+
+// The hint line fades in and out
+pub fn hint_line_color(dynamic_data: &DynamicGraphicsData, time: f64) -> [f32; 4] {
+    let pulse = 0.5 + 0.5 * (time * 2.0 * std::f64::consts::PI / HINT_PULSE_PERIOD).sin() as f32;
+    let [r, g, b] = dynamic_data.hint_line_color;
+    [r, g, b, 0.3 + 0.7 * pulse]
+}
+
+// Orthographic view of model space onto a canvas, shared by the GL renderers
+pub struct View {
+    pub window_size: (u32, u32), // device pixels
+    pub matrix: TMat4<f32>,
+    pixel_ratio: f32,
+    bounds: (f32, f32, f32, f32), // padded, before letterboxing
+    viewport: TVec4<f32>,
+}
+
+impl View {
+    pub fn new(window_size: (u32, u32)) -> View {
+        let mut out = View {
+            window_size,
+            matrix: nalgebra_glm::identity(),
+            pixel_ratio: 1.0,
+            bounds: (-3.0, 3.0, -3.0, 3.0),
+            viewport: nalgebra_glm::make_vec4(&[0., 0., window_size.0 as f32, window_size.1 as f32]),
+        };
+        out.update_matrix();
+        out
+    }
+
+    pub fn pixel_ratio(&self) -> f32 { self.pixel_ratio }
+
+    pub fn set_bounds(&mut self, lower: (f32, f32), upper: (f32, f32)) {
+        self.bounds = padded_bounds(lower, upper);
+        self.update_matrix();
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32, pixel_ratio: f32) {
+        self.window_size = (width, height);
+        self.pixel_ratio = pixel_ratio;
+        self.viewport = nalgebra_glm::make_vec4(&[0., 0., width as f32, height as f32]);
+        self.update_matrix();
+    }
+
+    fn update_matrix(&mut self) {
+        let (left, right, bottom, top) = letterboxed(self.bounds, self.window_size);
+        self.matrix = nalgebra_glm::ortho(left, right, bottom, top, 0.1, 1000.0);
+    }
+
+    pub fn unproject(&self, x: i32, y: i32) -> (f32, f32) {
+        let (x, y) = (x as f32 * self.pixel_ratio, y as f32 * self.pixel_ratio);
+        let unprojected = nalgebra_glm::unproject(
+            // Need to invert the y since canvas +Y goes downwards
+            &nalgebra_glm::make_vec3(&[x, self.window_size.1 as f32 - y, 0.]),
+            &nalgebra_glm::identity(),
+            &self.matrix,
+            self.viewport
+        );
+        (unprojected.x, unprojected.y)
+    }
+
+    pub fn matrix_array(&self) -> [f32; 16] {
+        let mut out = [0.; 16];
+        out.clone_from_slice(self.matrix.as_slice());
+        out
+    }
+}
+
+// Vertex circles expanded into two triangles each, for renderers that can't draw instances
+pub struct PointQuads {
+    pub positions: Vec<f32>,
+    pub uvs: Vec<f32>,
+    pub textures: Vec<f32>,
+    pub indices: Vec<u32>,
+}
+
+impl PointQuads {
+    pub fn from_instances(instances: &[f32]) -> PointQuads {
+        let mut out = PointQuads { positions: vec![], uvs: vec![], textures: vec![], indices: vec![] };
+        for (idx, instance) in instances.chunks(4).enumerate() {
+            let (x, y, half_width, texture) = (instance[0], instance[1], instance[2], instance[3]);
+            let offset = idx as u32 * 4;
+            out.positions.extend_from_slice(&[
+                x - half_width, y - half_width, // bottom left
+                x + half_width, y - half_width, // bottom right
+                x + half_width, y + half_width, // top right
+                x - half_width, y + half_width, // top left
+            ]);
+            out.uvs.extend_from_slice(&[0.0, 1.0, 1.0, 1.0, 1.0, 0.0, 0.0, 0.0]);
+            out.textures.extend_from_slice(&[texture; 4]);
+            out.indices.extend_from_slice(&[offset, offset + 1, offset + 2, offset, offset + 2, offset + 3]);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn padding_scales_with_the_puzzle() {
+        let (left, right, bottom, top) = padded_bounds((-1.0, -0.5), (1.0, 0.5));
+        assert_eq!(padded_bounds((-100.0, -50.0), (100.0, 50.0)), (left * 100.0, right * 100.0, bottom * 100.0, top * 100.0));
+        // Padded by the same amount on both axes, from the wider one
+        assert_eq!((left, right, bottom, top), (-1.4, 1.4, -0.9, 0.9));
+        // A single point still gets some room around it
+        assert_eq!(padded_bounds((2.0, 3.0), (2.0, 3.0)), (1.0, 3.0, 2.0, 4.0));
+    }
+}
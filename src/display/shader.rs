@@ -26,6 +26,9 @@ impl Shader {
         let vert_shader = compile_shader(&gl, WebGlRenderingContext::VERTEX_SHADER, vert_shader)?;
         let frag_shader = compile_shader(&gl, WebGlRenderingContext::FRAGMENT_SHADER, frag_shader)?;
         let program = link_program(&gl, &vert_shader, &frag_shader)?;
+        // The program keeps what it needs, so the shaders can go once it's linked
+        gl.delete_shader(Some(&vert_shader));
+        gl.delete_shader(Some(&frag_shader));
 
         let uniforms = RefCell::new(HashMap::new());
 
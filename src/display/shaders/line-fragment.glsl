@@ -1,5 +1,7 @@
 precision mediump float;
 
+uniform vec4 color;
+
 void main() {
-    gl_FragColor = vec4(0.0, 0.0, 0.0, 1.0);
+    gl_FragColor = color;
 }
\ No newline at end of file
@@ -0,0 +1,18 @@
+attribute vec2 corner; // of a square from -1, -1 to 1, 1
+attribute vec4 instance; // x, y, half width and texture index of the point
+
+varying vec2 fragmentUV;
+varying vec3 centerColor;
+
+uniform mat4 viewMatrix;
+
+void main() {
+    fragmentUV = vec2(0.5 + 0.5 * corner.x, 0.5 - 0.5 * corner.y);
+    if (instance.w < 0.0) {
+        // Connected to a vertex it shouldn't be
+        centerColor = vec3(1.0, 0.3, 0.3);
+    } else {
+        centerColor = vec3(1.0);
+    }
+    gl_Position = viewMatrix * vec4(instance.xy + corner * instance.z, -1.0, 1.0);
+}
\ No newline at end of file
@@ -9,7 +9,8 @@ uniform mat4 viewMatrix;
 
 void main() {
     fragmentUV = uv;
-    if (int(texture_index) == 0) {
+    if (texture_index < 0.0) {
+        // Connected to a vertex it shouldn't be
         centerColor = vec3(1.0, 0.3, 0.3);
     } else {
         centerColor = vec3(1.0);
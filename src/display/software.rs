@@ -0,0 +1,289 @@
+use super::renderer::*;
+use super::super::geometry::{StaticGraphicsData, DynamicGraphicsData};
+
+// Mirror the point shaders
+const POINT_RING_COLOR: [f32; 3] = [0.0, 0.0, 0.0];
+const POINT_CENTER_COLOR: [f32; 3] = [1.0, 1.0, 1.0];
+const POINT_WRONG_CENTER_COLOR: [f32; 3] = [1.0, 0.3, 0.3];
+
+// Draws into an RGBA buffer in memory the same way `Graphics` draws with WebGL, minus antialiasing.
+// Row 0 is the top of the image, like a canvas.
+pub struct SoftwareRenderer {
+    width: u32,
+    height: u32,
+    pixels: Vec<f32>, // RGBA, kept as floats so blending doesn't round at every step
+    pixel_ratio: f32,
+    clear_color: [f32; 4],
+    static_data: StaticGraphicsData,
+    padded_bounds: (f32, f32, f32, f32),
+    bounds: (f32, f32, f32, f32), // left, right, bottom, top, after letterboxing
+}
+
+impl SoftwareRenderer {
+    pub fn new(width: u32, height: u32) -> SoftwareRenderer {
+        SoftwareRenderer {
+            width,
+            height,
+            pixels: vec![0.0; (width * height * 4) as usize],
+            pixel_ratio: 1.0,
+            clear_color: DEFAULT_CLEAR_COLOR,
+            static_data: StaticGraphicsData::default(),
+            padded_bounds: (-3.0, 3.0, -3.0, 3.0),
+            bounds: letterboxed((-3.0, 3.0, -3.0, 3.0), (width, height)),
+        }
+    }
+
+    pub fn width(&self) -> u32 { self.width }
+    pub fn height(&self) -> u32 { self.height }
+
+    // Whatever was last drawn, 4 bytes per pixel, row by row from the top
+    pub fn to_rgba(&self) -> Vec<u8> {
+        self.pixels.iter().map(|&c| (c.clamp(0.0, 1.0) * 255.0).round() as u8).collect()
+    }
+
+    // Binary PPM, which any image viewer opens. Alpha is dropped since the clear color is opaque.
+    pub fn to_ppm(&self) -> Vec<u8> {
+        let mut out = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        for pixel in self.to_rgba().chunks(4) { out.extend_from_slice(&pixel[0..3]); }
+        out
+    }
+
+    // Model space to pixel space, where pixel centers are at half coordinates
+    fn project(&self, x: f32, y: f32) -> (f32, f32) {
+        let (left, right, bottom, top) = self.bounds;
+        ((x - left) / (right - left) * self.width as f32, (top - y) / (top - bottom) * self.height as f32)
+    }
+
+    // Same as blending with SRC_ALPHA, ONE_MINUS_SRC_ALPHA, but alpha composites so it stays opaque
+    fn blend(&mut self, x: u32, y: u32, color: [f32; 4]) {
+        let idx = ((y * self.width + x) * 4) as usize;
+        let dst = &mut self.pixels[idx..idx + 4];
+        for channel in 0..3 { dst[channel] = color[channel] * color[3] + dst[channel] * (1.0 - color[3]); }
+        dst[3] = color[3] + dst[3] * (1.0 - color[3]);
+    }
+
+    // Pixels whose centers fall inside the triangle, with the barycentric weights of each center
+    fn fill_triangle<F: FnMut([f32; 3]) -> Option<[f32; 4]>>(&mut self, corners: [(f32, f32); 3], mut shade: F) {
+        let [a, b, c] = corners;
+        let area = (b.0 - a.0) * (c.1 - a.1) - (c.0 - a.0) * (b.1 - a.1);
+        if area == 0.0 { return }
+
+        let min_x = a.0.min(b.0).min(c.0).floor().max(0.0) as u32;
+        let max_x = (a.0.max(b.0).max(c.0).ceil().max(0.0) as u32).min(self.width);
+        let min_y = a.1.min(b.1).min(c.1).floor().max(0.0) as u32;
+        let max_y = (a.1.max(b.1).max(c.1).ceil().max(0.0) as u32).min(self.height);
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let p = (x as f32 + 0.5, y as f32 + 0.5);
+                let wa = ((b.0 - p.0) * (c.1 - p.1) - (c.0 - p.0) * (b.1 - p.1)) / area;
+                let wb = ((c.0 - p.0) * (a.1 - p.1) - (a.0 - p.0) * (c.1 - p.1)) / area;
+                let wc = 1.0 - wa - wb;
+                if wa < 0.0 || wb < 0.0 || wc < 0.0 { continue }
+                if let Some(color) = shade([wa, wb, wc]) { self.blend(x, y, color); }
+            }
+        }
+    }
+
+    fn draw_triangles(&mut self, indices: &[u32]) {
+        for triangle in indices.chunks(3) {
+            let positions = &self.static_data.triangle_position_vertices;
+            let corners = [0, 1, 2].map(|i| {
+                let idx = triangle[i] as usize;
+                self.project(positions[idx * 2], positions[idx * 2 + 1])
+            });
+            let first = triangle[0] as usize * 4;
+            let mut color = [0.0; 4];
+            color.copy_from_slice(&self.static_data.triangle_color_vertices[first..first + 4]);
+            self.fill_triangle(corners, |_| Some(color));
+        }
+    }
+
+    // Lines are drawn as rectangles `width` CSS pixels across
+    fn draw_lines(&mut self, vertices: &[f32], color: [f32; 4], width: f32) {
+        let width = width * self.pixel_ratio;
+        for line in vertices.chunks(4) {
+            let (start, end) = (self.project(line[0], line[1]), self.project(line[2], line[3]));
+            let length = ((end.0 - start.0).powi(2) + (end.1 - start.1).powi(2)).sqrt();
+            if length == 0.0 { continue }
+
+            let half = width / 2.0;
+            let normal = (-(end.1 - start.1) / length * half, (end.0 - start.0) / length * half);
+            let corners = [
+                (start.0 + normal.0, start.1 + normal.1),
+                (end.0 + normal.0, end.1 + normal.1),
+                (end.0 - normal.0, end.1 - normal.1),
+                (start.0 - normal.0, start.1 - normal.1),
+            ];
+            self.fill_triangle([corners[0], corners[1], corners[2]], |_| Some(color));
+            self.fill_triangle([corners[0], corners[2], corners[3]], |_| Some(color));
+        }
+    }
+
+    fn draw_points(&mut self, instances: &[f32]) {
+        let quads = PointQuads::from_instances(instances);
+        let (positions, uvs) = (&quads.positions, &quads.uvs);
+        for triangle in quads.indices.chunks(3) {
+            let idx = [triangle[0] as usize, triangle[1] as usize, triangle[2] as usize];
+            let corners = idx.map(|i| self.project(positions[i * 2], positions[i * 2 + 1]));
+            let center = if quads.textures[idx[0]] < 0.0 { POINT_WRONG_CENTER_COLOR } else { POINT_CENTER_COLOR };
+            self.fill_triangle(corners, |weights| {
+                let u: f32 = (0..3).map(|k| weights[k] * uvs[idx[k] * 2]).sum();
+                let v: f32 = (0..3).map(|k| weights[k] * uvs[idx[k] * 2 + 1]).sum();
+                let distance = ((u - 0.5).powi(2) + (v - 0.5).powi(2)).sqrt();
+                if distance >= 0.5 { return None }
+                let [r, g, b] = if distance >= 0.4 { POINT_RING_COLOR } else { center };
+                Some([r, g, b, 1.0])
+            });
+        }
+    }
+}
+
+impl Renderer for SoftwareRenderer {
+    fn set_bounds(&mut self, lower: (f32, f32), upper: (f32, f32)) {
+        self.padded_bounds = padded_bounds(lower, upper);
+        self.bounds = letterboxed(self.padded_bounds, (self.width, self.height));
+    }
+
+    fn resize(&mut self, width: u32, height: u32, pixel_ratio: f32) {
+        *self = SoftwareRenderer {
+            width,
+            height,
+            pixels: vec![0.0; (width * height * 4) as usize],
+            pixel_ratio,
+            clear_color: self.clear_color,
+            static_data: std::mem::take(&mut self.static_data),
+            padded_bounds: self.padded_bounds,
+            bounds: letterboxed(self.padded_bounds, (width, height)),
+        };
+    }
+
+    fn unproject(&self, x: i32, y: i32) -> (f32, f32) {
+        let (left, right, bottom, top) = self.bounds;
+        let (x, y) = (x as f32 * self.pixel_ratio, y as f32 * self.pixel_ratio);
+        (
+            left + x / self.width as f32 * (right - left),
+            top - y / self.height as f32 * (top - bottom),
+        )
+    }
+
+    fn set_static_data(&mut self, static_data: &StaticGraphicsData) {
+        self.static_data = static_data.clone();
+    }
+
+    fn draw(&mut self, dynamic_data: &DynamicGraphicsData, time: f64) {
+        for pixel in self.pixels.chunks_mut(4) { pixel.copy_from_slice(&self.clear_color); }
+        self.draw_triangles(&dynamic_data.triangle_indices);
+        self.draw_lines(&dynamic_data.line_vertices, LINE_COLOR, LINE_WIDTH);
+        self.draw_lines(&dynamic_data.wrong_line_vertices, WRONG_LINE_COLOR, LINE_WIDTH);
+        self.draw_lines(&dynamic_data.hint_line_vertices, hint_line_color(dynamic_data, time), HINT_LINE_WIDTH);
+        self.draw_points(&dynamic_data.point_instances);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use super::super::super::geometry::PuzzleData;
+    use super::super::super::puzzle_state::{EdgeMode, Hint, PuzzleState, Rules};
+
+    const SIZE: u32 = 128;
+    const TOLERANCE: u8 = 2; // per channel, for float differences between platforms
+
+    fn render(data: &PuzzleData, state: &PuzzleState) -> SoftwareRenderer {
+        let mut renderer = SoftwareRenderer::new(SIZE, SIZE);
+        renderer.set_bounds(data.get_lower_bounds(), data.get_upper_bounds());
+        let dynamic_data = data.get_dynamic_graphics_data(state, &None, &None, &state.hint(data));
+        renderer.set_static_data(&data.get_static_graphics_data());
+        renderer.draw(&dynamic_data, 0.0);
+        renderer
+    }
+
+    // The first triangle revealed, plus a wrong edge with the hint pointing at it
+    fn playing(data: &PuzzleData) -> PuzzleState {
+        let mut state = PuzzleState::from_data(data, Rules { edge_mode: EdgeMode::Relaxed });
+        for id in data.get_edges_for_triangle(0).iter() { state.connect_edge(data, &data.get_edge(*id)); }
+        let wrong = (1..data.num_vertices() as u32).find(|&v| data.get_edge_id(&(0, v)).is_none());
+        if let Some(v) = wrong { state.connect_edge(data, &(0, v)); }
+        state
+    }
+
+    fn check_golden(path: &Path, image: &[u8]) -> Result<(), String> {
+        if std::env::var_os("UPDATE_GOLDEN").is_some() {
+            fs::write(path, image).unwrap();
+            return Ok(())
+        }
+        let expected = fs::read(path)
+            .map_err(|_| format!("{} is missing, run with UPDATE_GOLDEN=1 to create it", path.display()))?;
+        let matches = expected.len() == image.len()
+            && expected.iter().zip(image).all(|(a, b)| a.max(b) - a.min(b) <= TOLERANCE);
+        if matches { return Ok(()) }
+
+        let actual = std::env::temp_dir().join(path.file_name().unwrap());
+        fs::write(&actual, image).unwrap();
+        Err(format!("{} doesn't match, see {}", path.display(), actual.display()))
+    }
+
+    #[test]
+    fn puzzles_match_golden_images() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let mut puzzles: Vec<PathBuf> = fs::read_dir(root.join("puzzles")).unwrap().map(|e| e.unwrap().path()).collect();
+        puzzles.sort();
+
+        let mut failures = vec![];
+        for puzzle in &puzzles {
+            let data = PuzzleData::parse(&fs::read_to_string(puzzle).unwrap()).unwrap();
+            let mut solved = PuzzleState::from_data(&data, Rules { edge_mode: EdgeMode::Relaxed });
+            solved.reveal_all(&data);
+
+            let name = puzzle.file_stem().unwrap().to_string_lossy();
+            for (stage, state) in &[("playing", playing(&data)), ("solved", solved)] {
+                let path = root.join("src/display/golden").join(format!("{}-{}.ppm", name, stage));
+                if let Err(e) = check_golden(&path, &render(&data, state).to_ppm()) { failures.push(e); }
+            }
+        }
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+
+    #[test]
+    fn remove_hint_matches_golden_image() {
+        // Just a wrong edge, drawn when the pulsing hint over it is at its brightest
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let data = PuzzleData::parse(&fs::read_to_string(root.join("puzzles/1.txt")).unwrap()).unwrap();
+        let mut state = PuzzleState::from_data(&data, Rules { edge_mode: EdgeMode::Relaxed });
+        let n = data.num_vertices() as u32;
+        let wrong = (0..n).flat_map(|a| (a + 1..n).map(move |b| (a, b))).find(|e| data.get_edge_id(e).is_none()).unwrap();
+        state.connect_edge(&data, &wrong);
+        let hint = state.hint(&data);
+        assert!(matches!(hint, Some(Hint::Remove(_))));
+
+        let mut renderer = SoftwareRenderer::new(SIZE, SIZE);
+        renderer.set_bounds(data.get_lower_bounds(), data.get_upper_bounds());
+        renderer.set_static_data(&data.get_static_graphics_data());
+        renderer.draw(&data.get_dynamic_graphics_data(&state, &None, &None, &hint), 300.0);
+        check_golden(&root.join("src/display/golden/hint-remove.ppm"), &renderer.to_ppm()).unwrap();
+    }
+
+    #[test]
+    fn unproject_inverts_project() {
+        let mut renderer = SoftwareRenderer::new(200, 100);
+        renderer.set_bounds((-1.0, -1.0), (1.0, 1.0));
+        assert_eq!(renderer.project(0.0, 0.0), (100.0, 50.0));
+        // Letterboxed, so the extra width shows more of model space rather than stretching it
+        assert_eq!(renderer.unproject(50, 25), (-1.4, 0.7));
+
+        // Events come in CSS pixels, a quarter of which is 25 device pixels at twice the density
+        renderer.resize(400, 200, 2.0);
+        assert_eq!(renderer.project(0.0, 0.0), (200.0, 100.0));
+        assert_eq!(renderer.unproject(50, 25), (-1.4, 0.7));
+    }
+
+    #[test]
+    fn letterboxing_keeps_the_aspect_ratio() {
+        // Wide puzzle in a square output grows vertically, tall output for a square puzzle too
+        assert_eq!(letterboxed((0.0, 4.0, 0.0, 2.0), (100, 100)), (0.0, 4.0, -1.0, 3.0));
+        assert_eq!(letterboxed((0.0, 2.0, 0.0, 2.0), (100, 200)), (0.0, 2.0, -1.0, 3.0));
+        assert_eq!(letterboxed((0.0, 2.0, 0.0, 2.0), (50, 50)), (0.0, 2.0, 0.0, 2.0));
+    }
+}
@@ -9,63 +9,102 @@ pub enum Event {
     MouseMove(i32, i32),
     MouseUp(i32, i32),
     MouseLeave,
+    Undo,
+    Redo,
+    Hint,
+    Reset,
+    ClearMistakes,
+    RevealAll,
+}
+
+// A DOM listener that stays registered until the handler is detached
+struct Listener {
+    target: web_sys::EventTarget,
+    kind: &'static str,
+    closure: Closure<dyn FnMut(web_sys::Event)>,
 }
 
 pub struct EventHandler {
     event_queue: Vec<Event>,
+    listeners: Vec<Listener>,
 }
 
 impl EventHandler {
     pub fn init_from_canvas(canvas: &web_sys::HtmlCanvasElement) -> Result<Rc<RefCell<EventHandler>>, JsValue> {
         let out = Rc::new(RefCell::new(EventHandler{
             event_queue: vec![],
+            listeners: vec![],
         }));
 
-        {
-            let handler = out.clone();
-            let closure = Closure::wrap(Box::new(move |event: web_sys::PointerEvent| {
-                if let Ok(mut h) = handler.try_borrow_mut() {
-                    h.add_event(Event::MouseDown(event.offset_x(), event.offset_y()));
-                }
-            }) as Box<dyn FnMut(_)>);
-            canvas.add_event_listener_with_callback("mousedown", closure.as_ref().unchecked_ref())?;
-            closure.forget();
-        }
+        let mouse = |event: &web_sys::Event| event.unchecked_ref::<web_sys::MouseEvent>().clone();
+        EventHandler::listen(&out, canvas, "mousedown", move |e| {
+            let e = mouse(e);
+            Some(Event::MouseDown(e.offset_x(), e.offset_y()))
+        })?;
+        EventHandler::listen(&out, canvas, "mousemove", move |e| {
+            let e = mouse(e);
+            Some(Event::MouseMove(e.offset_x(), e.offset_y()))
+        })?;
+        EventHandler::listen(&out, canvas, "mouseup", move |e| {
+            let e = mouse(e);
+            Some(Event::MouseUp(e.offset_x(), e.offset_y()))
+        })?;
+        EventHandler::listen(&out, canvas, "mouseleave", |_| Some(Event::MouseLeave))?;
 
-        {
-            let handler = out.clone();
-            let closure = Closure::wrap(Box::new(move |event: web_sys::PointerEvent| {
-                if let Ok(mut h) = handler.try_borrow_mut() {
-                    h.add_event(Event::MouseMove(event.offset_x(), event.offset_y()));
-                }
-            }) as Box<dyn FnMut(_)>);
-            canvas.add_event_listener_with_callback("mousemove", closure.as_ref().unchecked_ref())?;
-            closure.forget();
-        }
+        // Keys go to the focused element, which usually isn't the canvas, so listen on the whole window
+        let window = web_sys::window().ok_or("No global window exists")?;
+        EventHandler::listen(&out, &window, "keydown", |event| {
+            let event = event.unchecked_ref::<web_sys::KeyboardEvent>();
+            if is_typing(event) { return None }
+            let modified = event.ctrl_key() || event.meta_key();
+            let plain = !modified && !event.alt_key();
+            let key = event.key().to_ascii_lowercase();
+            let game_event = match key.as_str() {
+                "z" if modified && event.shift_key() => Event::Redo,
+                "z" if modified => Event::Undo,
+                "h" if plain && !event.shift_key() => Event::Hint,
+                // Anything that throws away progress takes a deliberate chord so it doesn't happen by accident
+                "r" if plain && event.shift_key() => Event::Reset,
+                "c" if plain && event.shift_key() => Event::ClearMistakes,
+                "s" if plain && event.shift_key() => Event::RevealAll,
+                _ => return None,
+            };
+            event.prevent_default();
+            Some(game_event)
+        })?;
 
-        {
-            let handler = out.clone();
-            let closure = Closure::wrap(Box::new(move |event: web_sys::PointerEvent| {
-                if let Ok(mut h) = handler.try_borrow_mut() {
-                    h.add_event(Event::MouseUp(event.offset_x(), event.offset_y()));
-                }
-            }) as Box<dyn FnMut(_)>);
-            canvas.add_event_listener_with_callback("mouseup", closure.as_ref().unchecked_ref())?;
-            closure.forget();
-        }
+        Ok(out)
+    }
 
-        {
-            let handler = out.clone();
-            let closure = Closure::wrap(Box::new(move |_: web_sys::PointerEvent| {
-                if let Ok(mut h) = handler.try_borrow_mut() {
-                    h.add_event(Event::MouseLeave);
-                }
-            }) as Box<dyn FnMut(_)>);
-            canvas.add_event_listener_with_callback("mouseleave", closure.as_ref().unchecked_ref())?;
-            closure.forget();
-        }
+    // Queues whatever `to_event` makes of each DOM event of type `kind`. Listeners only hold a weak
+    // reference so that dropping the handler is enough to detach them.
+    fn listen<F: FnMut(&web_sys::Event) -> Option<Event> + 'static>(
+        handler: &Rc<RefCell<EventHandler>>,
+        target: &web_sys::EventTarget,
+        kind: &'static str,
+        mut to_event: F,
+    ) -> Result<(), JsValue> {
+        let weak = Rc::downgrade(handler);
+        let closure = Closure::wrap(Box::new(move |event: web_sys::Event| {
+            let handler = match weak.upgrade() {
+                Some(handler) => handler,
+                None => return,
+            };
+            if let Some(game_event) = to_event(&event) {
+                if let Ok(mut h) = handler.try_borrow_mut() { h.add_event(game_event); }
+            }
+        }) as Box<dyn FnMut(_)>);
+        target.add_event_listener_with_callback(kind, closure.as_ref().unchecked_ref())?;
+        handler.borrow_mut().listeners.push(Listener { target: target.clone(), kind, closure });
+        Ok(())
+    }
 
-        Ok(out)
+    // Stops listening and forgets anything that hasn't been handled yet
+    pub fn detach(&mut self) {
+        for listener in self.listeners.drain(..) {
+            let _ = listener.target.remove_event_listener_with_callback(listener.kind, listener.closure.as_ref().unchecked_ref());
+        }
+        self.event_queue.clear();
     }
 
     fn add_event(&mut self, event: Event) {
@@ -75,4 +114,17 @@ impl EventHandler {
     pub fn pending(&mut self) -> Box<dyn Iterator<Item = Event>> {
         Box::new(std::mem::replace(&mut self.event_queue, vec![]).into_iter())
     }
-}
\ No newline at end of file
+}
+
+// Keys meant for a text field elsewhere on the page aren't shortcuts
+fn is_typing(event: &web_sys::KeyboardEvent) -> bool {
+    let target = match event.target().and_then(|t| t.dyn_into::<web_sys::HtmlElement>().ok()) {
+        Some(target) => target,
+        None => return false,
+    };
+    matches!(target.tag_name().as_str(), "INPUT" | "TEXTAREA" | "SELECT") || target.is_content_editable()
+}
+
+impl Drop for EventHandler {
+    fn drop(&mut self) { self.detach(); }
+}
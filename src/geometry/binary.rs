@@ -0,0 +1,346 @@
+use super::{GeometryError, PuzzleData, PuzzleMetadata};
+use super::parser::{self, RawPuzzle};
+
+// Compact binary encoding for big generated puzzles, which are much faster to load than float text.
+//
+//     "VXPZ"                      magic
+//     u8                          format version
+//     u8                          bits per quantized coordinate
+//     varint x3                   number of vertices, colors and triangles
+//     f32 x4 (little endian)      lower x, lower y, upper x, upper y of the vertex bounds
+//     u64 (little endian)         fingerprint of the puzzle before quantization
+//     u8 + fields                 metadata presence flags, then strings (varint length + UTF-8) and par (varint)
+//     zigzag varint x2 per vertex quantized x, y as deltas from the previous vertex
+//     u8 x4 per color             r, g, b, a palette entries
+//     varints per triangle        v0 as a zigzag delta from the previous v0, v1 and v2 as zigzag deltas
+//                                 from v0, then the palette index
+//
+// Coordinates are snapped to a grid spanning the puzzle bounds, so a decoded puzzle matches the
+// original exactly except that vertices may move by up to half a grid step. Decoding and encoding
+// again always produces the same bytes. Fingerprints are worked out from exact coordinates, so the
+// original's is stored to keep saves and replays working between the text and binary versions of a
+// puzzle.
+
+const MAGIC: &[u8; 4] = b"VXPZ";
+const VERSION: u8 = 1;
+const QUANTIZATION_BITS: u8 = 20;
+
+const HAS_TITLE: u8 = 1;
+const HAS_AUTHOR: u8 = 1 << 1;
+const HAS_DIFFICULTY: u8 = 1 << 2;
+const HAS_PAR: u8 = 1 << 3;
+
+struct Quantizer {
+    lower: (f32, f32),
+    step: (f64, f64),
+    max: u32,
+}
+
+impl Quantizer {
+    fn new(lower: (f32, f32), upper: (f32, f32), bits: u8) -> Quantizer {
+        let max = ((1u64 << bits) - 1) as u32;
+        let step = |lo: f32, hi: f32| if hi > lo { (hi as f64 - lo as f64) / max as f64 } else { 0.0 };
+        Quantizer { lower, step: (step(lower.0, upper.0), step(lower.1, upper.1)), max }
+    }
+
+    fn quantize(&self, vertex: (f32, f32)) -> (u32, u32) {
+        let axis = |value: f32, lower: f32, step: f64| {
+            if step == 0.0 { return 0 }
+            (((value as f64 - lower as f64) / step).round() as u32).min(self.max)
+        };
+        (axis(vertex.0, self.lower.0, self.step.0), axis(vertex.1, self.lower.1, self.step.1))
+    }
+
+    fn dequantize(&self, quantized: (u32, u32)) -> (f32, f32) {
+        (
+            (self.lower.0 as f64 + quantized.0 as f64 * self.step.0) as f32,
+            (self.lower.1 as f64 + quantized.1 as f64 * self.step.1) as f32,
+        )
+    }
+}
+
+pub fn to_binary(data: &PuzzleData) -> Result<Vec<u8>, GeometryError> {
+    let mut out = MAGIC.to_vec();
+    out.push(VERSION);
+    out.push(QUANTIZATION_BITS);
+    write_varint(&mut out, data.vertices.len() as u64);
+    write_varint(&mut out, data.colors.len() as u64);
+    write_varint(&mut out, data.triangles.len() as u64);
+
+    let (lower, upper) = if data.vertices.is_empty() { ((0., 0.), (0., 0.)) } else { (data.lower_bounds, data.upper_bounds) };
+    for bound in &[lower.0, lower.1, upper.0, upper.1] {
+        out.extend_from_slice(&bound.to_le_bytes());
+    }
+    out.extend_from_slice(&data.fingerprint.to_le_bytes());
+
+    write_metadata(&mut out, &data.metadata);
+
+    let quantizer = Quantizer::new(lower, upper, QUANTIZATION_BITS);
+    let quantized: Vec<(u32, u32)> = data.vertices.iter().map(|&v| quantizer.quantize(v)).collect();
+    let mut previous = (0i64, 0i64);
+    for &(x, y) in &quantized {
+        write_varint(&mut out, zigzag(x as i64 - previous.0));
+        write_varint(&mut out, zigzag(y as i64 - previous.1));
+        previous = (x as i64, y as i64);
+    }
+
+    for color in &data.colors {
+        out.extend(color.iter().map(|c| (c * 255.0).round() as u8));
+    }
+
+    let mut previous_v0 = 0i64;
+    for (idx, triangle) in data.triangles.iter().enumerate() {
+        let corners = [quantized[triangle[0] as usize], quantized[triangle[1] as usize], quantized[triangle[2] as usize]];
+        if corners[0] == corners[1] || corners[1] == corners[2] || corners[0] == corners[2] {
+            return Err(GeometryError::InvalidBinary {
+                offset: out.len(),
+                reason: format!("vertices of triangle {} are too close together to encode", idx),
+            });
+        }
+
+        let v0 = triangle[0] as i64;
+        write_varint(&mut out, zigzag(v0 - previous_v0));
+        write_varint(&mut out, zigzag(triangle[1] as i64 - v0));
+        write_varint(&mut out, zigzag(triangle[2] as i64 - v0));
+        write_varint(&mut out, triangle[3] as u64);
+        previous_v0 = v0;
+    }
+
+    // Vertices so close that snapping swaps their order would get different canonical ids, which
+    // saves and replays refer to them by
+    if from_binary(&out)?.canonical_vertex_ids != data.canonical_vertex_ids {
+        return Err(GeometryError::InvalidBinary {
+            offset: out.len(),
+            reason: "some vertices are too close together to encode".to_string(),
+        });
+    }
+
+    Ok(out)
+}
+
+pub fn from_binary(bytes: &[u8]) -> Result<PuzzleData, GeometryError> {
+    let mut reader = ByteReader { bytes, offset: 0 };
+
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err(reader.error("not a binary puzzle".to_string()));
+    }
+    let version = reader.byte()?;
+    if version != VERSION {
+        return Err(reader.error(format!("unsupported version {}, expected {}", version, VERSION)));
+    }
+    let bits = reader.byte()?;
+    if bits == 0 || bits > 32 {
+        return Err(reader.error(format!("unsupported quantization of {} bits", bits)));
+    }
+
+    let num_vertices = reader.count()?;
+    let num_colors = reader.count()?;
+    let num_triangles = reader.count()?;
+
+    let mut bounds = [0f32; 4];
+    for bound in bounds.iter_mut() {
+        let mut le_bytes = [0u8; 4];
+        le_bytes.copy_from_slice(reader.take(4)?);
+        *bound = f32::from_le_bytes(le_bytes);
+        if !bound.is_finite() { return Err(reader.error("puzzle bounds are not finite".to_string())) }
+    }
+    let mut le_bytes = [0u8; 8];
+    le_bytes.copy_from_slice(reader.take(8)?);
+    let fingerprint = u64::from_le_bytes(le_bytes);
+
+    let mut raw = RawPuzzle { metadata: read_metadata(&mut reader)?, ..RawPuzzle::default() };
+
+    let quantizer = Quantizer::new((bounds[0], bounds[1]), (bounds[2], bounds[3]), bits);
+    let mut previous = (0i64, 0i64);
+    for _ in 0..num_vertices {
+        let x = previous.0.saturating_add(unzigzag(reader.varint()?));
+        let y = previous.1.saturating_add(unzigzag(reader.varint()?));
+        if x < 0 || y < 0 || x > quantizer.max as i64 || y > quantizer.max as i64 {
+            return Err(reader.error("vertex lies outside of the puzzle bounds".to_string()));
+        }
+        raw.vertices.push(quantizer.dequantize((x as u32, y as u32)));
+        previous = (x, y);
+    }
+
+    for _ in 0..num_colors {
+        let mut color = [0.0; 4];
+        for (component, &byte) in color.iter_mut().zip(reader.take(4)?) {
+            *component = byte as f32 / 255.0;
+        }
+        raw.colors.push(color);
+    }
+
+    let mut previous_v0 = 0i64;
+    for idx in 0..num_triangles {
+        let v0 = previous_v0.saturating_add(unzigzag(reader.varint()?));
+        let v1 = v0.saturating_add(unzigzag(reader.varint()?));
+        let v2 = v0.saturating_add(unzigzag(reader.varint()?));
+        let color = reader.varint()?;
+        let as_index = |value: i64| if value < 0 || value > u32::MAX as i64 { u32::MAX } else { value as u32 };
+        let triangle = [as_index(v0), as_index(v1), as_index(v2), as_index(color as i64)];
+        parser::check_triangle(&triangle, raw.vertices.len(), raw.colors.len())
+            .map_err(|reason| reader.error(format!("triangle {}: {}", idx, reason)))?;
+        raw.triangles.push(triangle);
+        previous_v0 = v0;
+    }
+
+    if reader.offset != bytes.len() {
+        return Err(reader.error("unexpected data after the last triangle".to_string()));
+    }
+
+    let mut out = PuzzleData::from_raw(raw);
+    out.fingerprint = fingerprint;
+    Ok(out)
+}
+
+fn write_metadata(out: &mut Vec<u8>, metadata: &PuzzleMetadata) {
+    let mut flags = 0;
+    if metadata.title.is_some() { flags |= HAS_TITLE; }
+    if metadata.author.is_some() { flags |= HAS_AUTHOR; }
+    if metadata.difficulty.is_some() { flags |= HAS_DIFFICULTY; }
+    if metadata.par.is_some() { flags |= HAS_PAR; }
+    out.push(flags);
+
+    for s in [&metadata.title, &metadata.author, &metadata.difficulty].iter().filter_map(|s| s.as_ref()) {
+        write_varint(out, s.len() as u64);
+        out.extend_from_slice(s.as_bytes());
+    }
+    if let Some(par) = metadata.par { write_varint(out, par as u64); }
+}
+
+fn read_metadata(reader: &mut ByteReader) -> Result<PuzzleMetadata, GeometryError> {
+    let flags = reader.byte()?;
+    if flags & !(HAS_TITLE | HAS_AUTHOR | HAS_DIFFICULTY | HAS_PAR) != 0 {
+        return Err(reader.error(format!("unknown metadata flags {:#x}", flags)));
+    }
+
+    let mut out = PuzzleMetadata::default();
+    if flags & HAS_TITLE != 0 { out.title = Some(reader.string()?); }
+    if flags & HAS_AUTHOR != 0 { out.author = Some(reader.string()?); }
+    if flags & HAS_DIFFICULTY != 0 { out.difficulty = Some(reader.string()?); }
+    if flags & HAS_PAR != 0 {
+        let par = reader.varint()?;
+        if par > u32::MAX as u64 { return Err(reader.error("par is too large".to_string())) }
+        out.par = Some(par as u32);
+    }
+    Ok(out)
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    fn error(&self, reason: String) -> GeometryError {
+        GeometryError::InvalidBinary { offset: self.offset, reason }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], GeometryError> {
+        if self.bytes.len() - self.offset < len {
+            return Err(self.error("unexpected end of data".to_string()));
+        }
+        let out = &self.bytes[self.offset..self.offset + len];
+        self.offset += len;
+        Ok(out)
+    }
+
+    fn byte(&mut self) -> Result<u8, GeometryError> { Ok(self.take(1)?[0]) }
+
+    fn varint(&mut self) -> Result<u64, GeometryError> {
+        let mut out = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            out |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 { return Ok(out) }
+        }
+        Err(self.error("varint is too long".to_string()))
+    }
+
+    // Element counts are bounded by the remaining data so a corrupt header can't cause a huge allocation
+    fn count(&mut self) -> Result<usize, GeometryError> {
+        let count = self.varint()?;
+        if count > (self.bytes.len() - self.offset) as u64 {
+            return Err(self.error(format!("count of {} is larger than the data", count)));
+        }
+        Ok(count as usize)
+    }
+
+    fn string(&mut self) -> Result<String, GeometryError> {
+        let len = self.count()?;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| self.error("metadata is not valid UTF-8".to_string()))
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn zigzag(value: i64) -> u64 { ((value << 1) ^ (value >> 63)) as u64 }
+fn unzigzag(value: u64) -> i64 { (value >> 1) as i64 ^ -((value & 1) as i64) }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn puzzle_files() -> Vec<(std::path::PathBuf, PuzzleData)> {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/puzzles");
+        std::fs::read_dir(dir).unwrap().map(|entry| {
+            let path = entry.unwrap().path();
+            let data = PuzzleData::parse(&std::fs::read_to_string(&path).unwrap()).unwrap();
+            (path, data)
+        }).collect()
+    }
+
+    #[test]
+    fn puzzle_files_round_trip_through_binary() {
+        for (path, original) in puzzle_files() {
+            let encoded = to_binary(&original).unwrap();
+            let decoded = from_binary(&encoded).unwrap();
+            assert_eq!(decoded.triangles, original.triangles);
+            assert_eq!(decoded.colors, original.colors);
+            assert_eq!(decoded.metadata, original.metadata);
+            assert_eq!(to_binary(&decoded).unwrap(), encoded, "{} doesn't encode the same twice", path.display());
+        }
+    }
+
+    #[test]
+    fn binary_puzzles_keep_their_identity() {
+        for (path, original) in puzzle_files() {
+            let decoded = from_binary(&to_binary(&original).unwrap()).unwrap();
+            assert_eq!(decoded.fingerprint(), original.fingerprint(), "{} has a new fingerprint", path.display());
+            assert_eq!(decoded.canonical_vertex_ids, original.canonical_vertex_ids);
+        }
+    }
+
+    #[test]
+    fn vertices_move_by_at_most_half_a_grid_step() {
+        for (path, original) in puzzle_files() {
+            let decoded = from_binary(&to_binary(&original).unwrap()).unwrap();
+            let max = ((1u64 << QUANTIZATION_BITS) - 1) as f64;
+            let half_step = |axis: fn(&(f32, f32)) -> f32| {
+                (axis(&original.upper_bounds) as f64 - axis(&original.lower_bounds) as f64) / max / 2.0
+            };
+            let allowed = (half_step(|v| v.0), half_step(|v| v.1));
+            for (idx, (a, b)) in original.vertices.iter().zip(&decoded.vertices).enumerate() {
+                // Plus the rounding of the decoded coordinate back to an f32
+                let slack = |value: f32| value.abs() as f64 * f32::EPSILON as f64;
+                assert!((a.0 as f64 - b.0 as f64).abs() <= allowed.0 + slack(b.0), "{} vertex {} moved in x", path.display(), idx);
+                assert!((a.1 as f64 - b.1 as f64).abs() <= allowed.1 + slack(b.1), "{} vertex {} moved in y", path.display(), idx);
+            }
+            assert_eq!(decoded.vertices.len(), original.vertices.len());
+        }
+    }
+
+    #[test]
+    fn vertices_that_would_swap_are_rejected() {
+        // Much closer in x than a grid step, so they only stay apart by y, which orders them the other way
+        let data = PuzzleData::parse("0 0\n1 0\n0 1\n0.0000001 -0.5\n1 1\n255 0 0\n0 1 2 0\n1 3 4 0").unwrap();
+        assert!(to_binary(&data).unwrap_err().to_string().contains("some vertices are too close together"));
+    }
+}
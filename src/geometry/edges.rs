@@ -0,0 +1,101 @@
+// Every edge of every triangle with a dense id, in compressed sparse row layout so that going from
+// an edge to its triangles or from a vertex to its edges is a slice instead of a hash lookup:
+//
+//     edges                  (v0, v1) with v0 <= v1, sorted, so an edge's id is its index
+//     triangle_edges         ids of the three edges of each triangle
+//     edge_triangles         triangles of edge i are edge_triangles[edge_offsets[i]..edge_offsets[i + 1]]
+//     vertex_edges           edges of vertex v are vertex_edges[vertex_offsets[v]..vertex_offsets[v + 1]]
+#[derive(Debug, Default)]
+pub struct EdgeTable {
+    edges: Vec<(u32, u32)>,
+    triangle_edges: Vec<[u32; 3]>,
+    edge_offsets: Vec<u32>,
+    edge_triangles: Vec<u32>,
+    vertex_offsets: Vec<u32>,
+    vertex_edges: Vec<u32>,
+}
+
+fn csr<I: Iterator<Item = (usize, u32)> + Clone>(num_rows: usize, entries: I) -> (Vec<u32>, Vec<u32>) {
+    let mut offsets = vec![0u32; num_rows + 1];
+    for (row, _) in entries.clone() { offsets[row + 1] += 1; }
+    for i in 0..num_rows { offsets[i + 1] += offsets[i]; }
+
+    let mut next = offsets.clone();
+    let mut values = vec![0u32; offsets[num_rows] as usize];
+    for (row, value) in entries {
+        values[next[row] as usize] = value;
+        next[row] += 1;
+    }
+    (offsets, values)
+}
+
+fn triangle_edge_pairs(triangle: &[u32; 4]) -> [(u32, u32); 3] {
+    let mut sorted = [triangle[0], triangle[1], triangle[2]];
+    sorted.sort();
+    [(sorted[0], sorted[1]), (sorted[1], sorted[2]), (sorted[0], sorted[2])]
+}
+
+impl EdgeTable {
+    pub fn new(num_vertices: usize, triangles: &[[u32; 4]]) -> EdgeTable {
+        let mut edges: Vec<(u32, u32)> = triangles.iter().flat_map(|t| triangle_edge_pairs(t).to_vec()).collect();
+        edges.sort();
+        edges.dedup();
+
+        let id_of = |edge: &(u32, u32)| edges.binary_search(edge).unwrap() as u32;
+        let triangle_edges: Vec<[u32; 3]> = triangles.iter().map(|t| {
+            let pairs = triangle_edge_pairs(t);
+            [id_of(&pairs[0]), id_of(&pairs[1]), id_of(&pairs[2])]
+        }).collect();
+
+        let (edge_offsets, edge_triangles) = csr(edges.len(), triangle_edges.iter().enumerate()
+            .flat_map(|(t, ids)| ids.iter().map(move |&id| (id as usize, t as u32))));
+
+        // Loaders reject triangles that repeat a vertex, so every edge has two different ends
+        let (vertex_offsets, vertex_edges) = csr(num_vertices, edges.iter().enumerate()
+            .flat_map(|(id, &(v0, v1))| [(v0 as usize, id as u32), (v1 as usize, id as u32)]));
+
+        EdgeTable { edges, triangle_edges, edge_offsets, edge_triangles, vertex_offsets, vertex_edges }
+    }
+
+    pub fn len(&self) -> usize { self.edges.len() }
+    pub fn edge(&self, id: u32) -> (u32, u32) { self.edges[id as usize] }
+
+    // Id of an edge given with its lower vertex first
+    pub fn find(&self, edge: &(u32, u32)) -> Option<u32> {
+        self.edges.binary_search(edge).ok().map(|id| id as u32)
+    }
+
+    pub fn triangles_of_edge(&self, id: u32) -> &[u32] {
+        &self.edge_triangles[self.edge_offsets[id as usize] as usize..self.edge_offsets[id as usize + 1] as usize]
+    }
+
+    pub fn edges_of_triangle(&self, triangle: u32) -> [u32; 3] { self.triangle_edges[triangle as usize] }
+
+    pub fn edges_of_vertex(&self, vertex: u32) -> &[u32] {
+        match self.vertex_offsets.get(vertex as usize + 1) {
+            Some(&end) => &self.vertex_edges[self.vertex_offsets[vertex as usize] as usize..end as usize],
+            None => &[],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn links_edges_triangles_and_vertices() {
+        // Two triangles sharing the edge 0 2
+        let table = EdgeTable::new(4, &[[0, 1, 2, 0], [2, 3, 0, 0]]);
+        let pairs: Vec<(u32, u32)> = (0..table.len() as u32).map(|id| table.edge(id)).collect();
+        assert_eq!(pairs, vec![(0, 1), (0, 2), (0, 3), (1, 2), (2, 3)]);
+
+        let shared = table.find(&(0, 2)).unwrap();
+        assert_eq!(table.triangles_of_edge(shared), &[0, 1]);
+        assert_eq!(table.find(&(1, 3)), None);
+        assert_eq!(table.edges_of_triangle(1), [1, 4, 2]);
+        assert_eq!(table.edges_of_vertex(0), &[0, 1, 2]);
+        assert_eq!(table.edges_of_vertex(3), &[2, 4]);
+        assert!(table.edges_of_vertex(9).is_empty());
+    }
+}
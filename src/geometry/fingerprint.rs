@@ -0,0 +1,119 @@
+use super::{compare_positions, PuzzleData};
+
+// Identities that stay the same however a puzzle's vertices and triangles are ordered, so a puzzle
+// re-exported by another tool is still recognized. Triangles are identified by the positions of their
+// corners and their color rather than by indices, and metadata doesn't count, so fixing a typo in the
+// title doesn't invalidate saves or replays.
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+// 64 bit FNV-1a, which is stable across platforms and releases unlike std's hashers
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Fnv { Fnv(FNV_OFFSET) }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn write_u32(&mut self, value: u32) { self.write(&value.to_le_bytes()); }
+    fn write_u64(&mut self, value: u64) { self.write(&value.to_le_bytes()); }
+    fn write_f32(&mut self, value: f32) { self.write_u32((value + 0.0).to_bits()); } // -0 and 0 are the same place
+
+    // Sorting first makes the result independent of the order the values came in
+    fn write_unordered(&mut self, mut values: Vec<u64>) {
+        values.sort();
+        self.write_u64(values.len() as u64);
+        for value in values { self.write_u64(value); }
+    }
+
+    fn finish(&self) -> u64 { self.0 }
+}
+
+pub fn fingerprint(data: &PuzzleData) -> u64 {
+    let triangles = data.triangles.iter().map(|t| {
+        let mut corners = [data.vertices[t[0] as usize], data.vertices[t[1] as usize], data.vertices[t[2] as usize]];
+        corners.sort_by(|&a, &b| compare_positions(a, b));
+
+        let mut hash = Fnv::new();
+        for &(x, y) in &corners {
+            hash.write_f32(x);
+            hash.write_f32(y);
+        }
+        for &component in &data.colors[t[3] as usize] { hash.write_f32(component); }
+        hash.finish()
+    }).collect();
+
+    let mut out = Fnv::new();
+    out.write_unordered(triangles);
+    out.finish()
+}
+
+// Numbers vertices by position, so the same vertex gets the same id in any ordering of the puzzle.
+// Vertices that aren't part of any triangle come last since they don't count towards the fingerprint.
+// Returns the id of each vertex.
+pub fn canonical_vertex_ids(data: &PuzzleData) -> Vec<u32> {
+    let mut used = vec![false; data.vertices.len()];
+    for t in &data.triangles {
+        for &v in &t[0..3] { used[v as usize] = true; }
+    }
+
+    let mut order: Vec<u32> = (0..data.vertices.len() as u32).collect();
+    order.sort_by(|&a, &b| {
+        used[b as usize].cmp(&used[a as usize])
+            .then(compare_positions(data.vertices[a as usize], data.vertices[b as usize]))
+            .then(a.cmp(&b))
+    });
+
+    let mut out = vec![0; data.vertices.len()];
+    for (id, &v) in order.iter().enumerate() { out[v as usize] = id as u32; }
+    out
+}
+
+// Progress on a puzzle, from the puzzle's fingerprint and the canonical ids of connected edges
+pub fn progress_fingerprint<I: Iterator<Item = (u32, u32)>>(puzzle: u64, canonical_edges: I) -> u64 {
+    let edges = canonical_edges.map(|(a, b)| {
+        let mut hash = Fnv::new();
+        hash.write_u32(a.min(b));
+        hash.write_u32(a.max(b));
+        hash.finish()
+    }).collect();
+
+    let mut out = Fnv::new();
+    out.write_u64(puzzle);
+    out.write_unordered(edges);
+    out.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ignores_ordering_winding_and_metadata() {
+        let original = PuzzleData::parse("0 0\n1 0\n1 1\n0 1\n255 0 0\n0 0 255\n0 1 2 0\n0 2 3 1").unwrap();
+        let reordered = PuzzleData::parse(
+            "vertex-puzzle 2\n[meta]\ntitle = Square\n[vertices]\n0 1\n1 1\n0 0\n1 0\n\
+             [colors]\n0 0 255\n255 0 0\n[triangles]\n1 0 2 0\n3 1 2 1"
+        ).unwrap();
+        assert_eq!(original.fingerprint(), reordered.fingerprint());
+
+        let ids = |data: &PuzzleData, v: (f32, f32)| data.canonical_vertex_id(data.vertices.iter().position(|&p| p == v).unwrap() as u32);
+        for &v in &[(0., 0.), (1., 0.), (1., 1.), (0., 1.)] {
+            assert_eq!(ids(&original, v), ids(&reordered, v));
+        }
+    }
+
+    #[test]
+    fn changes_with_geometry_and_color() {
+        let base = PuzzleData::parse("0 0\n1 0\n1 1\n255 0 0\n0 1 2 0").unwrap();
+        let moved = PuzzleData::parse("0 0\n1 0\n1 2\n255 0 0\n0 1 2 0").unwrap();
+        let recolored = PuzzleData::parse("0 0\n1 0\n1 1\n255 0 1\n0 1 2 0").unwrap();
+        assert_ne!(base.fingerprint(), moved.fingerprint());
+        assert_ne!(base.fingerprint(), recolored.fingerprint());
+    }
+}
@@ -0,0 +1,119 @@
+use serde::{Deserialize, Serialize};
+use super::{GeometryError, PuzzleData, PuzzleMetadata};
+use super::parser::{self, RawPuzzle};
+
+// JSON mirror of the sectioned text format, e.g.
+//
+//     {
+//         "metadata": { "title": "Square", "par": 5 },
+//         "vertices": [[-1, -1], [1, -1], [1, 1]],
+//         "colors": [[255, 0, 0], [0, 0, 255, 128]],
+//         "triangles": [[0, 1, 2, 1]]
+//     }
+//
+// Colors are 0-255 with an optional alpha, just like in text puzzles.
+#[derive(Serialize, Deserialize)]
+struct JsonPuzzle {
+    #[serde(default)]
+    metadata: PuzzleMetadata,
+    vertices: Vec<[f32; 2]>,
+    colors: Vec<Vec<u8>>,
+    triangles: Vec<[u32; 4]>,
+}
+
+pub fn from_json(json: &str) -> Result<PuzzleData, GeometryError> {
+    let parsed: JsonPuzzle = serde_json::from_str(json)
+        .map_err(|e| GeometryError::InvalidJson { reason: e.to_string() })?;
+
+    let mut raw = RawPuzzle { metadata: parsed.metadata, ..RawPuzzle::default() };
+
+    for (idx, vertex) in parsed.vertices.iter().enumerate() {
+        if !vertex[0].is_finite() || !vertex[1].is_finite() {
+            return Err(GeometryError::InvalidJson { reason: format!("vertex {} is not a pair of finite numbers", idx) });
+        }
+        raw.vertices.push((vertex[0], vertex[1]));
+    }
+
+    for (idx, color) in parsed.colors.iter().enumerate() {
+        if color.len() != 3 && color.len() != 4 {
+            return Err(GeometryError::InvalidJson { reason: format!("color {} should have 3 or 4 components", idx) });
+        }
+        let mut to_push = [1.0; 4];
+        for (component, &value) in to_push.iter_mut().zip(color) {
+            *component = value as f32 / 255.0;
+        }
+        raw.colors.push(to_push);
+    }
+
+    for (idx, triangle) in parsed.triangles.iter().enumerate() {
+        parser::check_triangle(triangle, raw.vertices.len(), raw.colors.len())
+            .map_err(|reason| GeometryError::InvalidJson { reason: format!("triangle {}: {}", idx, reason) })?;
+        raw.triangles.push(*triangle);
+    }
+
+    Ok(PuzzleData::from_raw(raw))
+}
+
+pub fn to_json(data: &PuzzleData) -> String {
+    let out = JsonPuzzle {
+        metadata: data.metadata.clone(),
+        vertices: data.vertices.iter().map(|&(x, y)| [x, y]).collect(),
+        colors: data.colors.iter().map(|color| {
+            let mut components: Vec<u8> = color.iter().map(|c| (c * 255.0).round() as u8).collect();
+            if components[3] == 255 { components.pop(); } // Leave out alpha for opaque colors
+            components
+        }).collect(),
+        triangles: data.triangles.clone(),
+    };
+
+    // Only plain numbers and strings in here, so this can't fail
+    serde_json::to_string(&out).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(data: &PuzzleData) -> String {
+        let mut out = vec![];
+        data.write_to(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn error(json: &str) -> String {
+        from_json(json).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn puzzle_files_round_trip_through_json() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/puzzles");
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let original = PuzzleData::parse(&std::fs::read_to_string(&path).unwrap()).unwrap();
+            let reparsed = from_json(&to_json(&original)).unwrap();
+            assert_eq!(text(&original), text(&reparsed), "{} changed going through JSON", path.display());
+        }
+    }
+
+    #[test]
+    fn metadata_fields_are_optional() {
+        let data = from_json(r#"{"metadata": {"par": 1}, "vertices": [[0, 0]], "colors": [], "triangles": []}"#).unwrap();
+        assert_eq!(data.metadata, PuzzleMetadata { par: Some(1), ..PuzzleMetadata::default() });
+        let data = from_json(r#"{"vertices": [[0, 0]], "colors": [], "triangles": []}"#).unwrap();
+        assert_eq!(data.metadata, PuzzleMetadata::default());
+    }
+
+    #[test]
+    fn invalid_puzzles_are_rejected() {
+        assert_eq!(
+            error(r#"{"vertices": [[0, 0], [1, 0], [0, 1]], "colors": [[255, 0]], "triangles": []}"#),
+            "invalid JSON puzzle: color 0 should have 3 or 4 components",
+        );
+        assert_eq!(
+            error(r#"{"vertices": [[0, 0], [1, 0], [0, 1]], "colors": [[255, 0, 0]], "triangles": [[0, 1, 3, 0]]}"#),
+            "invalid JSON puzzle: triangle 0: triangle references vertex 3 but only 3 vertices defined",
+        );
+        assert!(error(r#"{"vertices": [[0, 0]], "triangles": []}"#).contains("missing field `colors`"));
+        assert!(error(r#"{"metadata": {"par": "five"}, "vertices": [], "colors": [], "triangles": []}"#).contains("invalid type"));
+    }
+}
@@ -1,129 +1,191 @@
-use std::io::BufRead;
-use std::collections::{HashMap, HashSet};
-use super::puzzle_state::PuzzleState;
+use std::io::{BufRead, Write};
+use std::collections::HashSet;
+use serde::{Deserialize, Serialize};
+use super::puzzle_state::{Hint, PuzzleState, VertexStatus};
+
+mod parser;
+mod json;
+mod binary;
+mod writer;
+mod validate;
+mod normalize;
+mod spatial;
+mod fingerprint;
+mod edges;
+
+pub use validate::{Severity, ValidationIssue};
+
+// How far from a vertex, in puzzle units, a click still selects it
+pub const VERTEX_SELECT_RADIUS: f32 = 0.12;
 
 quick_error! {
     #[derive(Debug)]
     pub enum GeometryError {
-        ParseFailure { from(std::io::Error) from() }
-        InvalidVertex
-        InvalidTriangle
-        InvalidColor
+        Io(err: std::io::Error) {
+            from()
+            display("could not read puzzle: {}", err)
+        }
+        ParseFailure { line: usize, reason: String } {
+            display("line {}: {}", line, reason)
+        }
+        InvalidVertex { line: usize, reason: String } {
+            display("line {}: invalid vertex: {}", line, reason)
+        }
+        InvalidTriangle { line: usize, reason: String } {
+            display("line {}: {}", line, reason)
+        }
+        InvalidColor { line: usize, reason: String } {
+            display("line {}: invalid color: {}", line, reason)
+        }
+        InvalidMetadata { line: usize, reason: String } {
+            display("line {}: invalid metadata: {}", line, reason)
+        }
+        InvalidJson { reason: String } {
+            display("invalid JSON puzzle: {}", reason)
+        }
+        InvalidBinary { offset: usize, reason: String } {
+            display("invalid binary puzzle at byte {}: {}", offset, reason)
+        }
     }
 }
 
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PuzzleMetadata {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub difficulty: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub par: Option<u32>, // number of edges an expert needs to draw
+}
+
 #[derive(Debug)]
 pub struct PuzzleData {
     vertices: Vec<(f32, f32)>, // x, y
     triangles: Vec<[u32; 4]>, // v0, v1, v2, color
-    colors: Vec<[f32; 3]>, // r, g, b (0-1 float)
-    edge_to_triangles: HashMap<(u32, u32), Vec<usize>>, // v0, v1 -> triangle indices (edge indices are sorted)
-    triangle_to_edges: HashMap<u32, [(u32, u32); 3]>,
-    vertices_to_edges: HashMap<u32, HashSet<(u32, u32)>>,
+    colors: Vec<[f32; 4]>, // r, g, b, a (0-1 float)
+    metadata: PuzzleMetadata,
+    edges: edges::EdgeTable,
     lower_bounds: (f32, f32),
     upper_bounds: (f32, f32),
+    vertex_grid: spatial::VertexGrid,
+    fingerprint: u64,
+    canonical_vertex_ids: Vec<u32>, // see fingerprint.rs
+    vertices_by_canonical_id: Vec<u32>,
 }
 
 impl PuzzleData {
+    // Accepts both the legacy format and the sectioned format, see parser.rs for details
     pub fn from_reader<R: BufRead>(reader: &mut R) -> Result<PuzzleData, GeometryError> {
+        Ok(PuzzleData::from_raw(parser::parse(reader)?))
+    }
+
+    pub fn from_json(json: &str) -> Result<PuzzleData, GeometryError> {
+        json::from_json(json)
+    }
+
+    // Picks JSON or text parsing based on what the puzzle looks like
+    pub fn parse(puzzle: &str) -> Result<PuzzleData, GeometryError> {
+        if puzzle.trim_start().starts_with('{') {
+            PuzzleData::from_json(puzzle)
+        } else {
+            PuzzleData::from_reader(&mut puzzle.as_bytes())
+        }
+    }
+
+    pub fn to_json(&self) -> String { json::to_json(self) }
+
+    pub fn from_binary(bytes: &[u8]) -> Result<PuzzleData, GeometryError> {
+        binary::from_binary(bytes)
+    }
+
+    pub fn to_binary(&self) -> Result<Vec<u8>, GeometryError> { binary::to_binary(self) }
+
+    // Emits the canonical text format, which from_reader reads back into an identical puzzle
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        writer::write_text(self, writer)
+    }
+
+    // Structural checks beyond what parsing guarantees, most serious issues first
+    pub fn validate(&self) -> Vec<ValidationIssue> {
+        let mut out = validate::validate(self);
+        out.sort_by_key(|issue| std::cmp::Reverse(issue.severity()));
+        out
+    }
+
+    // A cleaned up copy of this puzzle, see normalize.rs for everything that changes
+    pub fn normalized(&self, tolerance: f32) -> PuzzleData {
+        normalize::normalize(self, tolerance)
+    }
+
+    fn from_raw(raw: parser::RawPuzzle) -> PuzzleData {
         let mut out = PuzzleData{
-            vertices: vec![],
-            triangles: vec![],
-            colors: vec![],
-            edge_to_triangles: HashMap::new(),
-            triangle_to_edges: HashMap::new(),
-            vertices_to_edges: HashMap::new(),
+            vertices: raw.vertices,
+            triangles: raw.triangles,
+            colors: raw.colors,
+            metadata: raw.metadata,
+            edges: edges::EdgeTable::default(),
             lower_bounds: (std::f32::MAX, std::f32::MAX),
             upper_bounds: (std::f32::MIN, std::f32::MIN),
+            vertex_grid: spatial::VertexGrid::new(&[], (0., 0.), (0., 0.)),
+            fingerprint: 0,
+            canonical_vertex_ids: vec![],
+            vertices_by_canonical_id: vec![],
         };
 
-        // Parse geometry and colors
-        for line in reader.lines() {
-            let l = line?;
-            let split: Vec<&str> = l.split_whitespace().collect();
-            match split.len() {
-                2 => { // vertex
-                    let to_push = (
-                        split[0].parse::<f32>().map_err(|_| GeometryError::InvalidVertex)?,
-                        split[1].parse::<f32>().map_err(|_| GeometryError::InvalidVertex)?
-                    );
-
-                    if to_push.0 < out.lower_bounds.0 { out.lower_bounds.0 = to_push.0; }
-                    if to_push.1 < out.lower_bounds.1 { out.lower_bounds.1 = to_push.1; }
-                    if to_push.0 > out.upper_bounds.0 { out.upper_bounds.0 = to_push.0; }
-                    if to_push.1 > out.upper_bounds.1 { out.upper_bounds.1 = to_push.1; }
-
-                    out.vertices.push(to_push);
-                },
-                3 => { // RGB color
-                    out.colors.push([
-                        split[0].parse::<u8>().map_err(|_| GeometryError::InvalidColor)? as f32 / 255.0,
-                        split[1].parse::<u8>().map_err(|_| GeometryError::InvalidColor)? as f32 / 255.0,
-                        split[2].parse::<u8>().map_err(|_| GeometryError::InvalidColor)? as f32 / 255.0
-                    ]);
-                },
-                4 => { // triangle
-                    let triangle_indices = split.iter().take(3).map(|s| {
-                        let idx = s.parse::<u32>().map_err(|_| GeometryError::InvalidTriangle)?;
-                        if idx as usize >= out.vertices.len() { Err(GeometryError::InvalidTriangle) } else { Ok(idx) }
-                    }).collect::<Result<Vec<u32>, GeometryError>>()?;
-                    
-                    // Check for duplicate vertices
-                    let mut triangle_index_integrity = triangle_indices.clone();
-                    triangle_index_integrity.sort();
-                    triangle_index_integrity.dedup();
-                    if triangle_index_integrity.len() < 3 {
-                        return Err(GeometryError::InvalidTriangle);
-                    }
-
-                    let color_idx = split[3].parse::<u32>().map_err(|_| GeometryError::InvalidTriangle)?;
-                    if color_idx as usize > out.colors.len() { return Err(GeometryError::InvalidTriangle); }
-
-                    out.triangles.push([
-                        triangle_indices[0],
-                        triangle_indices[1],
-                        triangle_indices[2],
-                        color_idx
-                    ]);
-                },
-                _ => return Err(GeometryError::ParseFailure)
-            }
+        for vertex in &out.vertices {
+            if vertex.0 < out.lower_bounds.0 { out.lower_bounds.0 = vertex.0; }
+            if vertex.1 < out.lower_bounds.1 { out.lower_bounds.1 = vertex.1; }
+            if vertex.0 > out.upper_bounds.0 { out.upper_bounds.0 = vertex.0; }
+            if vertex.1 > out.upper_bounds.1 { out.upper_bounds.1 = vertex.1; }
         }
+        out.vertex_grid = spatial::VertexGrid::new(&out.vertices, out.lower_bounds, out.upper_bounds);
 
-        // Construct edge to triangle and triangle to edge membership maps
-        for (idx, triangle_data) in (&out.triangles).iter().enumerate() {
-            let mut sorted = triangle_data[0..3].to_vec();
-            sorted.sort();
-            let triangle_to_edges = [(sorted[0], sorted[1]), (sorted[1], sorted[2]), (sorted[0], sorted[2])];
-            for (e0, e1) in &triangle_to_edges {
-                out.edge_to_triangles.entry((*e0, *e1)).or_insert(vec![]).push(idx);
-            }
-            out.triangle_to_edges.insert(idx as u32, triangle_to_edges);
-        }
+        out.edges = edges::EdgeTable::new(out.vertices.len(), &out.triangles);
 
-        // Construct vertex to edge map
-        for edge in out.edge_to_triangles.keys() {
-            out.vertices_to_edges.entry(edge.0).or_insert(HashSet::new()).insert(*edge);
-            out.vertices_to_edges.entry(edge.1).or_insert(HashSet::new()).insert(*edge);
+        out.fingerprint = fingerprint::fingerprint(&out);
+        out.canonical_vertex_ids = fingerprint::canonical_vertex_ids(&out);
+        out.vertices_by_canonical_id = vec![0; out.vertices.len()];
+        for (v, &id) in out.canonical_vertex_ids.iter().enumerate() {
+            out.vertices_by_canonical_id[id as usize] = v as u32;
         }
 
-        Ok(out)
+        out
     }
 
-    pub fn num_triangles(&self) -> usize { self.triangles.len() }
+    // Identifies the puzzle regardless of how it's ordered, ignoring metadata
+    pub fn fingerprint(&self) -> u64 { self.fingerprint }
 
-    pub fn triangles_with_edge(&self, edge: &(u32, u32)) -> Option<&Vec<usize>> {
-        self.edge_to_triangles.get(edge)
+    // Vertex ids that don't depend on how the puzzle is ordered, for anything stored outside the game
+    pub fn canonical_vertex_id(&self, vertex: u32) -> u32 { self.canonical_vertex_ids[vertex as usize] }
+    pub fn vertex_from_canonical_id(&self, id: u32) -> Option<u32> {
+        self.vertices_by_canonical_id.get(id as usize).cloned()
     }
 
-    pub fn num_edges_from_vertex(&self, vertex: u32) -> usize {
-        self.vertices_to_edges.get(&vertex).map(|v| v.len()).unwrap_or(0)
+    // Identifies progress on this puzzle by which edges are connected
+    pub fn progress_fingerprint<I: Iterator<Item = (u32, u32)>>(&self, edges: I) -> u64 {
+        let canonical_edges = edges.map(|e| (self.canonical_vertex_id(e.0), self.canonical_vertex_id(e.1)));
+        fingerprint::progress_fingerprint(self.fingerprint, canonical_edges)
     }
 
-    pub fn get_edges_for_triangle(&self, triangle: u32) -> Vec<(u32, u32)> {
-        self.triangle_to_edges[&triangle].to_vec()
+    pub fn num_vertices(&self) -> usize { self.vertices.len() }
+    pub fn num_triangles(&self) -> usize { self.triangles.len() }
+
+    // Edges of the puzzle have dense ids from 0 to num_edges, see edges.rs
+    pub fn num_edges(&self) -> usize { self.edges.len() }
+    pub fn get_edge(&self, id: u32) -> (u32, u32) { self.edges.edge(id) }
+    pub fn get_edge_id(&self, edge: &(u32, u32)) -> Option<u32> {
+        let ordered = if edge.0 > edge.1 { (edge.1, edge.0) } else { *edge };
+        self.edges.find(&ordered)
     }
 
+    pub fn triangles_with_edge(&self, id: u32) -> &[u32] { self.edges.triangles_of_edge(id) }
+    pub fn edges_from_vertex(&self, vertex: u32) -> &[u32] { self.edges.edges_of_vertex(vertex) }
+    pub fn num_edges_from_vertex(&self, vertex: u32) -> usize { self.edges_from_vertex(vertex).len() }
+    pub fn get_edges_for_triangle(&self, triangle: u32) -> [u32; 3] { self.edges.edges_of_triangle(triangle) }
+
     pub fn get_static_graphics_data(&self) -> StaticGraphicsData {
         StaticGraphicsData::from_data(self)
     }
@@ -133,35 +195,32 @@ impl PuzzleData {
         state: &PuzzleState,
         last_vertex: &Option<u32>,
         curr_pointer: &Option<(f32, f32)>,
+        hint: &Option<Hint>,
     ) -> DynamicGraphicsData {
         DynamicGraphicsData::from_data_and_state(
             self,
             state,
-            &InteractiveFeatures::from_data_and_interact_info(self, state, last_vertex, curr_pointer)
+            &InteractiveFeatures::from_data_and_interact_info(self, state, last_vertex, curr_pointer, hint)
         )
     }
 
+    // Closest vertex the player can still interact with, if any is within `threshold` of `point`
     pub fn get_vertex_near(&self, state: &PuzzleState, point: (f32, f32), threshold: f32) -> Option<u32> {
-        for (idx, vertex) in (&self.vertices).iter().enumerate() {
-            if (vertex.0 - point.0).hypot(vertex.1 - point.1) <= threshold
-            && state.should_be_interactable(self, idx as u32) {
-                return Some(idx as u32)
-            }
-        }
-        None
+        self.vertex_grid.nearest(&self.vertices, point, threshold, |idx| state.vertex_status(self, idx).is_interactable())
     }
 
+    pub fn get_vertex(&self, vertex: u32) -> (f32, f32) { self.vertices[vertex as usize] }
+    pub fn get_metadata(&self) -> &PuzzleMetadata { &self.metadata }
     pub fn get_lower_bounds(&self) -> (f32, f32) { self.lower_bounds }
     pub fn get_upper_bounds(&self) -> (f32, f32) { self.upper_bounds }
 }
 
 // Should only need to ever make one of these per puzzle
-#[derive(Debug)]
+#[derive(Clone, Debug, Default)]
 pub struct StaticGraphicsData {
     pub num_vertices: usize,
     pub triangle_position_vertices: Vec<f32>,
-    pub triangle_color_idx_vertices: Vec<f32>,
-    pub colors_uniform: Vec<f32>,
+    pub triangle_color_vertices: Vec<f32>, // r, g, b, a for every vertex
 }
 
 impl StaticGraphicsData {
@@ -169,25 +228,20 @@ impl StaticGraphicsData {
         let mut out = StaticGraphicsData {
             num_vertices: data.vertices.len(),
             triangle_position_vertices: vec![],
-            triangle_color_idx_vertices: vec![],
-            colors_uniform: vec![],
+            triangle_color_vertices: vec![],
         };
 
         for triangle in &data.triangles {
             // We need to make multiple copies of vertices for each triangle that uses them
-            // The second attribute of a triangle vertex is the color index in the color array uniform
-            let color_idx = triangle[3];
+            // Colors go with the vertices too, so there's no limit on how many a puzzle can have
+            let color = &data.colors[triangle[3] as usize];
             for &vert_idx in &triangle[0..3] {
                 let (x, y) = &data.vertices[vert_idx as usize];
                 out.triangle_position_vertices.append(&mut vec![*x, *y]);
-                out.triangle_color_idx_vertices.push(color_idx as f32);
+                out.triangle_color_vertices.extend_from_slice(color);
             }
         }
 
-        for color in &data.colors {
-            out.colors_uniform.append(&mut color.to_vec());
-        }
-
         out
     }
 }
@@ -195,12 +249,12 @@ impl StaticGraphicsData {
 // Need to make one one of these for every frame
 #[derive(Debug)]
 pub struct DynamicGraphicsData {
-    pub triangle_indices: Vec<u16>,
+    pub triangle_indices: Vec<u32>,
     pub line_vertices: Vec<f32>,
-    pub point_positions: Vec<f32>,
-    pub point_uvs: Vec<f32>,
-    pub point_textures: Vec<f32>,
-    pub point_indices: Vec<u16>,
+    pub wrong_line_vertices: Vec<f32>, // connected edges that aren't part of the puzzle
+    pub point_instances: Vec<f32>, // see point_instance, renderers turn each one into a circle
+    pub hint_line_vertices: Vec<f32>, // drawn pulsing on top of the other lines
+    pub hint_line_color: [f32; 3],
 }
 
 impl DynamicGraphicsData {
@@ -212,19 +266,25 @@ impl DynamicGraphicsData {
         let mut out = DynamicGraphicsData {
             triangle_indices: vec![],
             line_vertices: vec![],
-            point_positions: vec![],
-            point_uvs: vec![],
-            point_textures: vec![],
-            point_indices: vec![],
+            wrong_line_vertices: vec![],
+            point_instances: vec![],
+            hint_line_vertices: vec![],
+            hint_line_color: [0.0, 0.0, 0.0],
         };
 
-        for &(start, end) in state.get_connected_edges() {
+        for id in state.connected_edge_ids() {
+            let (start, end) = data.get_edge(id);
             let ((start_x, start_y), (end_x, end_y)) = (data.vertices[start as usize], data.vertices[end as usize]);
-            out.line_vertices.append(&mut vec![start_x, start_y, end_x, end_y]);
+            out.line_vertices.extend_from_slice(&[start_x, start_y, end_x, end_y]);
         }
 
-        for &idx in state.get_unlocked_triangles() {
-            let base = idx as u16 * 3;
+        for (start, end) in state.wrong_edges() {
+            let ((start_x, start_y), (end_x, end_y)) = (data.vertices[start as usize], data.vertices[end as usize]);
+            out.wrong_line_vertices.extend_from_slice(&[start_x, start_y, end_x, end_y]);
+        }
+
+        for idx in state.unlocked_triangles() {
+            let base = idx * 3;
             out.triangle_indices.append(&mut vec![base, base + 1, base + 2]);
         }
 
@@ -232,22 +292,25 @@ impl DynamicGraphicsData {
             out.line_vertices.append(&mut vec![x1, y1, x2, y2]);
         }
 
-        let mut idx_offset = 0;
-        for (idx, &p) in (&data.vertices).iter().enumerate() {
-            let remaining = data.num_edges_from_vertex(idx as u32) - state.get_permanent_edges_for_vertex(idx as u32);
-            let non_permanent = state.get_non_permanent_edges_for_vertex(idx as u32);
+        if let Some(hint) = interactive.hint {
+            let (start, end) = hint.edge();
+            let ((start_x, start_y), (end_x, end_y)) = (data.vertices[start as usize], data.vertices[end as usize]);
+            out.hint_line_vertices = vec![start_x, start_y, end_x, end_y];
+            // Remove hints always sit on a wrong edge, which is already red, so they need a color of their own
+            out.hint_line_color = match hint {
+                Hint::Remove(_) => [1.0, 0.65, 0.0],
+                Hint::Connect(_) => [1.0, 1.0, 1.0],
+            };
+        }
 
+        for (idx, &p) in (&data.vertices).iter().enumerate() {
             // Only skip drawing a vertex if it's 100% done and it doesn't have any extra connections.
             // If it has extra connections the player should be able to disconnect them still.
-            if remaining == 0 && non_permanent == 0 { continue }
+            let status = state.vertex_status(data, idx as u32);
+            if status.is_done() { continue }
 
             let multiplier = if interactive.selected_vertices.contains(&(idx as u32)) { 1.5 } else { 1.0 };
-            let mut quad_data = PointQuad::new(p, idx_offset, remaining, multiplier);
-            out.point_positions.append(&mut quad_data.positions);
-            out.point_uvs.append(&mut quad_data.uvs);
-            out.point_textures.append(&mut quad_data.textures);
-            out.point_indices.append(&mut quad_data.indices);
-            idx_offset += 4;
+            out.point_instances.extend_from_slice(&point_instance(p, &status, multiplier));
         }
 
         out
@@ -257,6 +320,7 @@ impl DynamicGraphicsData {
 pub struct InteractiveFeatures {
     active_edge: Option<((f32, f32), (f32, f32))>,
     selected_vertices: HashSet<u32>,
+    hint: Option<Hint>,
 }
 
 impl InteractiveFeatures {
@@ -264,14 +328,16 @@ impl InteractiveFeatures {
         data: &PuzzleData,
         state: &PuzzleState,
         last_vertex: &Option<u32>,
-        curr_pointer: &Option<(f32, f32)>
+        curr_pointer: &Option<(f32, f32)>,
+        hint: &Option<Hint>,
     ) -> InteractiveFeatures {
         let mut out = InteractiveFeatures {
             active_edge: None,
             selected_vertices: HashSet::new(),
+            hint: *hint,
         };
 
-        let curr_pointer_vert = curr_pointer.and_then(|p| data.get_vertex_near(state, p, 0.12));
+        let curr_pointer_vert = curr_pointer.and_then(|p| data.get_vertex_near(state, p, VERTEX_SELECT_RADIUS));
         if let Some(v) = last_vertex { out.selected_vertices.insert(*v); }
         if let Some(v) = curr_pointer_vert { out.selected_vertices.insert(v); }
         if let (Some(v), Some(p2)) = (last_vertex, curr_pointer) {
@@ -282,43 +348,108 @@ impl InteractiveFeatures {
     }
 }
 
-struct PointQuad {
-    positions: Vec<f32>,
-    uvs: Vec<f32>,
-    textures: Vec<f32>,
-    indices: Vec<u16>,
+// Where and how big a vertex's circle is, as x, y, half width and texture index. Vertices with wrong
+// edges get a negative texture index so the shader can flag them, whatever their remaining count is.
+fn point_instance(center: (f32, f32), status: &VertexStatus, multiplier: f32) -> [f32; 4] {
+    let remaining_f = status.remaining() as f32;
+    let half_width = multiplier * (0.07 + remaining_f * 0.015);
+    let texture = if status.is_over_connected() { -(status.surplus as f32) } else { remaining_f };
+    [center.0, center.1, half_width, texture]
 }
 
-impl PointQuad {
-    fn new(center: (f32, f32), offset: u16, remaining: usize, multiplier: f32) -> PointQuad {
-        let mut out = PointQuad {
-            positions: vec![],
-            uvs: vec![],
-            textures: vec![],
-            indices: vec![
-                offset, offset + 1, offset + 2, // triangle 1
-                offset, offset + 2, offset + 3 // triangle 2
-            ],
-        };
+// Orders positions by x, then y. NaNs compare equal to everything rather than panicking.
+fn compare_positions(a: (f32, f32), b: (f32, f32)) -> std::cmp::Ordering {
+    let axis = |a: f32, b: f32| a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal);
+    axis(a.0, b.0).then(axis(a.1, b.1))
+}
 
-        let remaining_f = remaining as f32;
-        let half_width = multiplier * (0.07 + remaining_f * 0.015);
+// Twice the signed area of the triangle o, a, b, which is positive when it winds counter-clockwise
+fn cross(o: (f32, f32), a: (f32, f32), b: (f32, f32)) -> f32 {
+    (a.0 - o.0) * (b.1 - o.1) - (a.1 - o.1) * (b.0 - o.0)
+}
 
-        out.positions.append(&mut vec![
-            center.0 - half_width, center.1 - half_width, // bottom left
-            center.0 + half_width, center.1 - half_width, // bottom right
-            center.0 + half_width, center.1 + half_width, // top right
-            center.0 - half_width, center.1 + half_width, // top left
-        ]);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        out.uvs.append(&mut vec![
-            0.0, 1.0, // bottom left
-            1.0, 1.0, // bottom right
-            1.0, 0.0, // top right
-            0.0, 0.0, // top left
-        ]);
+    fn assert_same_puzzle(a: &PuzzleData, b: &PuzzleData) {
+        assert_eq!(a.vertices, b.vertices);
+        assert_eq!(a.colors, b.colors);
+        assert_eq!(a.triangles, b.triangles);
+        assert_eq!(a.metadata, b.metadata);
+    }
 
-        out.textures.append(&mut vec![remaining_f, remaining_f, remaining_f, remaining_f]);
-        out
+    fn write_to_string(data: &PuzzleData) -> String {
+        let mut out = vec![];
+        data.write_to(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn puzzle_files_round_trip_through_text() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/puzzles");
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let original = PuzzleData::parse(&std::fs::read_to_string(&path).unwrap()).unwrap();
+
+            let written = write_to_string(&original);
+            let reparsed = PuzzleData::parse(&written).unwrap();
+            assert_same_puzzle(&original, &reparsed);
+
+            // The canonical form is a fixed point
+            assert_eq!(written, write_to_string(&reparsed), "{} is not canonical", path.display());
+        }
+    }
+
+    #[test]
+    fn metadata_and_alpha_round_trip_through_text() {
+        let puzzle = "vertex-puzzle 2\n\
+            [triangles]\n0 1 2 1\n\
+            [meta]\npar = 3\ntitle = Two colors #2\n\
+            [colors]\n255 0 0\n0 0 255 128\n\
+            [vertices]\n0 0\n1.5 0\n0 -0.25\n";
+        let original = PuzzleData::parse(puzzle).unwrap();
+        let reparsed = PuzzleData::parse(&write_to_string(&original)).unwrap();
+        assert_same_puzzle(&original, &reparsed);
+        assert_eq!(reparsed.metadata.title.as_deref(), Some("Two colors #2"));
+    }
+
+    #[test]
+    fn unwritable_metadata_is_rejected() {
+        let mut data = PuzzleData::parse(include_str!("../../puzzles/1.txt")).unwrap();
+        data.metadata.title = Some("Puzzle\n1".to_string());
+        assert!(data.write_to(&mut vec![]).is_err());
+        data.metadata.title = Some(" Puzzle 1".to_string());
+        assert!(data.write_to(&mut vec![]).is_err());
+    }
+
+    #[test]
+    fn wrong_edges_are_drawn_separately() {
+        use super::super::puzzle_state::{EdgeMode, Rules};
+        let data = PuzzleData::parse(include_str!("../../puzzles/1.txt")).unwrap();
+        let mut state = PuzzleState::from_data(&data, Rules { edge_mode: EdgeMode::Relaxed });
+        state.connect_edge(&data, &(0, 1));
+        state.connect_edge(&data, &(1, 3));
+
+        let graphics = data.get_dynamic_graphics_data(&state, &None, &None, &None);
+        assert_eq!(graphics.line_vertices, vec![-1., -1., 1., -1.]);
+        assert_eq!(graphics.wrong_line_vertices, vec![1., -1., -1., 1.]);
+        // Vertices 1 and 3 are flagged, 0 and 2 show how many edges they still need
+        let badges: Vec<f32> = graphics.point_instances.chunks(4).map(|p| p[3]).collect();
+        assert_eq!(badges, vec![3., -1., 3., -1.]);
+    }
+
+    #[test]
+    fn every_color_reaches_the_vertices() {
+        // One triangle per color, well past the 100 the triangle shader used to have room for
+        let mut puzzle = String::from("0 0\n1 0\n0 1\n");
+        for c in 0..150 { puzzle += &format!("{} 0 0\n", c); }
+        for c in 0..150 { puzzle += &format!("0 1 2 {}\n", c); }
+        let data = PuzzleData::parse(&puzzle).unwrap();
+
+        let graphics = data.get_static_graphics_data();
+        let last = &graphics.triangle_color_vertices[149 * 12..];
+        assert_eq!(last.len(), 12);
+        assert!(last.chunks(4).all(|color| color == [149.0 / 255.0, 0.0, 0.0, 1.0]));
+    }
+}
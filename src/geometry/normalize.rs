@@ -0,0 +1,130 @@
+use std::collections::{HashMap, HashSet};
+use super::{compare_positions, cross, PuzzleData};
+use super::parser::RawPuzzle;
+
+// Cleans up a puzzle so that equivalent puzzles from different sources come out identical:
+// - vertices closer than `tolerance` are merged into the one that sorts first
+// - triangles that collapse or repeat because of that are dropped
+// - identical colors are merged and unreferenced vertices and colors are dropped
+// - every triangle winds counter-clockwise, starting from its lowest vertex index
+// - vertices are sorted by position, triangles by their vertices and colors by first use
+pub fn normalize(data: &PuzzleData, tolerance: f32) -> PuzzleData {
+    // Merge nearby vertices, visiting them in sorted order so the result doesn't depend on input order
+    let mut by_position: Vec<u32> = (0..data.vertices.len() as u32).collect();
+    by_position.sort_by(|&a, &b| compare_positions(data.vertices[a as usize], data.vertices[b as usize]));
+
+    let cell_size = tolerance.max(f32::MIN_POSITIVE);
+    let cell = |p: (f32, f32)| ((p.0 / cell_size).floor() as i64, (p.1 / cell_size).floor() as i64);
+    let mut grid: HashMap<(i64, i64), Vec<u32>> = HashMap::new();
+    let mut merged_into: Vec<u32> = (0..data.vertices.len() as u32).collect();
+    for &idx in &by_position {
+        let p = data.vertices[idx as usize];
+        let (cx, cy) = cell(p);
+        let existing = (cx - 1..=cx + 1)
+            .flat_map(|x| (cy - 1..=cy + 1).map(move |y| (x, y)))
+            .filter_map(|c| grid.get(&c))
+            .flatten()
+            .find(|&&other| {
+                let q = data.vertices[other as usize];
+                (p.0 - q.0).hypot(p.1 - q.1) <= tolerance
+            })
+            .cloned();
+        match existing {
+            Some(representative) => merged_into[idx as usize] = representative,
+            None => grid.entry((cx, cy)).or_default().push(idx),
+        }
+    }
+
+    // Remap triangles onto merged vertices and identical colors, dropping any that fall apart
+    let mut first_color_with_value: HashMap<[u32; 4], u32> = HashMap::new();
+    let color_key = |c: &[f32; 4]| [c[0].to_bits(), c[1].to_bits(), c[2].to_bits(), c[3].to_bits()];
+    let canonical_color: Vec<u32> = data.colors.iter().enumerate()
+        .map(|(idx, c)| *first_color_with_value.entry(color_key(c)).or_insert(idx as u32))
+        .collect();
+
+    let mut seen: HashSet<[u32; 3]> = HashSet::new();
+    let mut triangles: Vec<[u32; 4]> = vec![];
+    for triangle in &data.triangles {
+        let mut t = [
+            merged_into[triangle[0] as usize],
+            merged_into[triangle[1] as usize],
+            merged_into[triangle[2] as usize],
+            canonical_color[triangle[3] as usize],
+        ];
+        if t[0] == t[1] || t[1] == t[2] || t[0] == t[2] { continue }
+        let winding = cross(data.vertices[t[0] as usize], data.vertices[t[1] as usize], data.vertices[t[2] as usize]);
+        if winding == 0.0 { continue }
+
+        let mut sorted = [t[0], t[1], t[2]];
+        sorted.sort();
+        if !seen.insert(sorted) { continue }
+
+        if winding < 0.0 { t.swap(1, 2); }
+        triangles.push(t);
+    }
+
+    // Renumber the vertices that are still referenced in sorted position order
+    let referenced: HashSet<u32> = triangles.iter().flat_map(|t| t[0..3].to_vec()).collect();
+    let mut new_vertex_idx: HashMap<u32, u32> = HashMap::new();
+    let mut out = RawPuzzle { metadata: data.metadata.clone(), ..RawPuzzle::default() };
+    for &idx in by_position.iter().filter(|idx| referenced.contains(idx)) {
+        new_vertex_idx.insert(idx, out.vertices.len() as u32);
+        out.vertices.push(data.vertices[idx as usize]);
+    }
+
+    for t in triangles.iter_mut() {
+        for v in t[0..3].iter_mut() { *v = new_vertex_idx[v]; }
+
+        // Rotate the lowest index to the front, which keeps the winding
+        let lowest = (0..3).min_by_key(|&i| t[i]).unwrap();
+        t[0..3].rotate_left(lowest);
+    }
+    triangles.sort();
+
+    // Number colors in order of first use by the sorted triangles
+    let mut new_color_idx: HashMap<u32, u32> = HashMap::new();
+    for t in triangles.iter_mut() {
+        let next = new_color_idx.len() as u32;
+        let color = *new_color_idx.entry(t[3]).or_insert_with(|| {
+            out.colors.push(data.colors[t[3] as usize]);
+            next
+        });
+        t[3] = color;
+    }
+    out.triangles = triangles;
+
+    PuzzleData::from_raw(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cleans_up_messy_puzzle() {
+        // Vertex 4 almost duplicates vertex 1, vertex 5 and color 2 are unused,
+        // color 3 repeats color 0, and the second triangle winds clockwise
+        let messy = PuzzleData::parse(
+            "1 1\n1 0\n0 0\n0 1\n1.0000001 0\n9 9\n\
+             255 0 0\n0 0 255\n0 255 0\n255 0 0\n\
+             2 1 0 1\n2 3 0 3\n2 4 0 0"
+        ).unwrap();
+        let clean = normalize(&messy, 1e-4);
+
+        assert_eq!(clean.vertices, vec![(0., 0.), (0., 1.), (1., 0.), (1., 1.)]);
+        assert_eq!(clean.triangles, vec![[0, 2, 3, 0], [0, 3, 1, 1]]);
+        assert_eq!(clean.colors, vec![[0., 0., 1., 1.], [1., 0., 0., 1.]]);
+        assert!(clean.validate().is_empty());
+    }
+
+    #[test]
+    fn normalizing_is_idempotent() {
+        let data = PuzzleData::parse(include_str!("../../puzzles/3.txt")).unwrap();
+        let once = normalize(&data, 1e-4);
+        let twice = normalize(&once, 1e-4);
+        assert_eq!(once.vertices, twice.vertices);
+        assert_eq!(once.triangles, twice.triangles);
+        assert_eq!(once.colors, twice.colors);
+        assert_eq!(once.num_triangles(), data.num_triangles());
+    }
+}
@@ -0,0 +1,323 @@
+use std::io::BufRead;
+use super::{GeometryError, PuzzleMetadata};
+
+// Puzzle files come in two flavors, told apart by their first meaningful line:
+//
+// Legacy files have no header and infer what each line is from its number of values:
+// `x y` is a vertex, `r g b` is a color and `v0 v1 v2 color` is a triangle.
+//
+// Version 2 files start with a `vertex-puzzle 2` header followed by named sections in any order:
+//
+//     vertex-puzzle 2
+//     [meta]
+//     title = Square
+//     author = Somebody
+//     difficulty = easy
+//     par = 5
+//     [vertices]
+//     -1 -1      # x y, with an optional z that is accepted and ignored
+//     [colors]
+//     255 0 0    # r g b, with an optional a (all 0-255)
+//     [triangles]
+//     0 1 2 0    # v0 v1 v2 color
+//
+// In both flavors `#` starts a comment that runs to the end of the line and blank lines are ignored.
+// The exception is `[meta]`, where `#` only starts a comment at the beginning of a line so that values
+// like `title = Puzzle #1` come through whole.
+
+pub const HEADER: &str = "vertex-puzzle";
+pub const CURRENT_VERSION: u32 = 2;
+
+// Everything a puzzle file describes, before any derived lookup tables are built
+#[derive(Debug, Default)]
+pub struct RawPuzzle {
+    pub vertices: Vec<(f32, f32)>,
+    pub colors: Vec<[f32; 4]>,
+    pub triangles: Vec<[u32; 4]>,
+    pub metadata: PuzzleMetadata,
+}
+
+// A single meaningful line of a puzzle file: comments are stripped and blank lines never make it here
+#[derive(Debug)]
+pub struct Line {
+    pub number: usize, // 1-based, as shown in a text editor
+    pub content: String,
+    pub tokens: Vec<String>,
+    pub uncommented: String, // the whole line, for metadata values that may contain `#`
+}
+
+impl Line {
+    pub fn text(&self) -> String { self.tokens.join(" ") }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Section {
+    Meta,
+    Vertices,
+    Colors,
+    Triangles,
+}
+
+pub fn read_lines<R: BufRead>(reader: &mut R) -> Result<Vec<Line>, GeometryError> {
+    let mut out = vec![];
+    for (idx, line) in reader.lines().enumerate() {
+        let l = line?;
+        let content = l.split('#').next().unwrap_or("").trim();
+        let tokens: Vec<String> = content.split_whitespace().map(String::from).collect();
+        if tokens.is_empty() { continue }
+        out.push(Line { number: idx + 1, content: content.to_string(), tokens, uncommented: l.trim().to_string() });
+    }
+    Ok(out)
+}
+
+pub fn parse<R: BufRead>(reader: &mut R) -> Result<RawPuzzle, GeometryError> {
+    let lines = read_lines(reader)?;
+    match lines.first() {
+        Some(first) if first.tokens[0] == HEADER => {
+            let version = first.tokens.get(1).and_then(|v| v.parse::<u32>().ok());
+            match version {
+                Some(CURRENT_VERSION) if first.tokens.len() == 2 => parse_sectioned(&lines[1..]),
+                _ => Err(GeometryError::ParseFailure {
+                    line: first.number,
+                    reason: format!("unsupported puzzle header `{}`, expected `{} {}`", first.text(), HEADER, CURRENT_VERSION),
+                }),
+            }
+        },
+        _ => parse_legacy(&lines),
+    }
+}
+
+fn parse_legacy(lines: &[Line]) -> Result<RawPuzzle, GeometryError> {
+    let mut out = RawPuzzle::default();
+    for line in lines {
+        match line.tokens.len() {
+            2 => out.vertices.push(parse_vertex(line)?),
+            3 => out.colors.push(parse_color(line)?),
+            4 => out.triangles.push(parse_triangle(line, out.vertices.len(), out.colors.len())?),
+            n => return Err(GeometryError::ParseFailure {
+                line: line.number,
+                reason: format!(
+                    "expected a vertex (2 values), color (3 values) or triangle (4 values) but found {} values",
+                    n
+                ),
+            }),
+        }
+    }
+    Ok(out)
+}
+
+fn parse_sectioned(lines: &[Line]) -> Result<RawPuzzle, GeometryError> {
+    let mut out = RawPuzzle::default();
+    let mut section: Option<Section> = None;
+    let mut seen_sections: Vec<Section> = vec![];
+
+    // Triangles may come before the vertices and colors they reference, so check them at the end
+    let mut triangle_lines: Vec<&Line> = vec![];
+
+    for line in lines {
+        if line.content.starts_with('[') {
+            let next = match line.content.as_str() {
+                "[meta]" => Section::Meta,
+                "[vertices]" => Section::Vertices,
+                "[colors]" => Section::Colors,
+                "[triangles]" => Section::Triangles,
+                _ => return Err(GeometryError::ParseFailure {
+                    line: line.number,
+                    reason: format!("unknown section `{}`", line.content),
+                }),
+            };
+            if seen_sections.contains(&next) {
+                return Err(GeometryError::ParseFailure {
+                    line: line.number,
+                    reason: format!("section `{}` appears more than once", line.content),
+                });
+            }
+            seen_sections.push(next);
+            section = Some(next);
+            continue;
+        }
+
+        match section {
+            Some(Section::Meta) => parse_metadata(line, &mut out.metadata)?,
+            Some(Section::Vertices) => out.vertices.push(parse_vertex(line)?),
+            Some(Section::Colors) => out.colors.push(parse_color(line)?),
+            Some(Section::Triangles) => triangle_lines.push(line),
+            None => return Err(GeometryError::ParseFailure {
+                line: line.number,
+                reason: format!("`{}` is not inside a section", line.text()),
+            }),
+        }
+    }
+
+    for line in triangle_lines {
+        out.triangles.push(parse_triangle(line, out.vertices.len(), out.colors.len())?);
+    }
+
+    Ok(out)
+}
+
+fn parse_metadata(line: &Line, metadata: &mut PuzzleMetadata) -> Result<(), GeometryError> {
+    let invalid = |reason: String| GeometryError::InvalidMetadata { line: line.number, reason };
+    let mut split = line.uncommented.splitn(2, '=');
+    let key = split.next().unwrap_or("").trim();
+    let value = split.next()
+        .map(|v| v.trim())
+        .ok_or_else(|| invalid(format!("expected `key = value` but found `{}`", line.uncommented)))?;
+    if value.is_empty() { return Err(invalid(format!("`{}` has no value", key))) }
+
+    let slot = match key {
+        "title" => &mut metadata.title,
+        "author" => &mut metadata.author,
+        "difficulty" => &mut metadata.difficulty,
+        "par" => {
+            if metadata.par.is_some() { return Err(invalid("`par` is set more than once".to_string())) }
+            metadata.par = Some(value.parse::<u32>().map_err(|_| {
+                invalid(format!("par `{}` is not a non-negative integer", value))
+            })?);
+            return Ok(());
+        },
+        _ => return Err(invalid(format!("unknown key `{}`", key))),
+    };
+
+    if slot.is_some() { return Err(invalid(format!("`{}` is set more than once", key))) }
+    *slot = Some(value.to_string());
+    Ok(())
+}
+
+pub fn parse_vertex(line: &Line) -> Result<(f32, f32), GeometryError> {
+    if line.tokens.len() != 2 && line.tokens.len() != 3 {
+        return Err(GeometryError::InvalidVertex {
+            line: line.number,
+            reason: format!("expected `x y` or `x y z` but found `{}`", line.text()),
+        });
+    }
+
+    let coordinate = |s: &str| match s.parse::<f32>() {
+        Ok(v) if v.is_finite() => Ok(v),
+        _ => Err(GeometryError::InvalidVertex {
+            line: line.number,
+            reason: format!("coordinate `{}` is not a finite number", s),
+        }),
+    };
+
+    // Puzzles are flat, so a z coordinate only has to be well-formed
+    if let Some(z) = line.tokens.get(2) { coordinate(z)?; }
+    Ok((coordinate(&line.tokens[0])?, coordinate(&line.tokens[1])?))
+}
+
+pub fn parse_color(line: &Line) -> Result<[f32; 4], GeometryError> {
+    if line.tokens.len() != 3 && line.tokens.len() != 4 {
+        return Err(GeometryError::InvalidColor {
+            line: line.number,
+            reason: format!("expected `r g b` or `r g b a` but found `{}`", line.text()),
+        });
+    }
+
+    let mut out = [1.0; 4];
+    for (component, token) in out.iter_mut().zip(&line.tokens) {
+        *component = token.parse::<u8>().map_err(|_| GeometryError::InvalidColor {
+            line: line.number,
+            reason: format!("component `{}` is not an integer from 0 to 255", token),
+        })? as f32 / 255.0;
+    }
+    Ok(out)
+}
+
+pub fn parse_triangle(line: &Line, num_vertices: usize, num_colors: usize) -> Result<[u32; 4], GeometryError> {
+    let invalid = |reason: String| GeometryError::InvalidTriangle { line: line.number, reason };
+    if line.tokens.len() != 4 {
+        return Err(invalid(format!("expected `v0 v1 v2 color` but found `{}`", line.text())));
+    }
+
+    let mut out = [0; 4];
+    for (idx, token) in line.tokens.iter().enumerate() {
+        out[idx] = token.parse::<u32>().map_err(|_| invalid(format!("index `{}` is not a non-negative integer", token)))?;
+    }
+
+    check_triangle(&out, num_vertices, num_colors).map_err(invalid)?;
+    Ok(out)
+}
+
+// Shared by every puzzle format: makes sure a triangle only references things that exist
+pub fn check_triangle(triangle: &[u32; 4], num_vertices: usize, num_colors: usize) -> Result<(), String> {
+    for &vertex in &triangle[0..3] {
+        if vertex as usize >= num_vertices {
+            return Err(format!("triangle references vertex {} but only {} vertices defined", vertex, num_vertices));
+        }
+    }
+
+    // Check for duplicate vertices
+    if triangle[0] == triangle[1] || triangle[1] == triangle[2] || triangle[0] == triangle[2] {
+        return Err(format!(
+            "triangle uses the same vertex more than once in `{} {} {}`", triangle[0], triangle[1], triangle[2]
+        ));
+    }
+
+    if triangle[3] as usize >= num_colors {
+        return Err(format!("triangle references color {} but only {} colors defined", triangle[3], num_colors));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(puzzle: &str) -> String {
+        parse(&mut puzzle.as_bytes()).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn comments_and_blank_lines_are_skipped() {
+        let raw = parse(&mut "# a square\n\n-1 -1\n1 -1  # bottom right\n   \n1 1\n255 0 0\n0 1 2 0 # the only triangle\n".as_bytes()).unwrap();
+        assert_eq!(raw.vertices, vec![(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0)]);
+        assert_eq!(raw.colors, vec![[1.0, 0.0, 0.0, 1.0]]);
+        assert_eq!(raw.triangles, vec![[0, 1, 2, 0]]);
+    }
+
+    #[test]
+    fn errors_name_the_line_in_the_file() {
+        // Line numbers count the comments and blank lines that were skipped
+        let mut puzzle = String::from("# made by hand\n# with care\n\n");
+        for v in 0..80 { puzzle += &format!("{} 0\n", v); }
+        puzzle += "255 0 0\n0 1 91 0\n";
+        assert_eq!(error(&puzzle), "line 85: triangle references vertex 91 but only 80 vertices defined");
+
+        assert_eq!(error("0 0\n1 0\n0 1\n255 0 0\n0 1 2 1"), "line 5: triangle references color 1 but only 1 colors defined");
+        assert_eq!(error("0 0\n1 0 2 3 4"), "line 2: expected a vertex (2 values), color (3 values) or triangle (4 values) but found 5 values");
+        assert_eq!(error("# x y\n0 zero"), "line 2: invalid vertex: coordinate `zero` is not a finite number");
+        assert_eq!(error("0 0\n256 0 0"), "line 2: invalid color: component `256` is not an integer from 0 to 255");
+    }
+
+    #[test]
+    fn sectioned_files_need_a_known_header() {
+        let raw = parse(&mut "vertex-puzzle 2\n[vertices]\n0 0\n".as_bytes()).unwrap();
+        assert_eq!(raw.vertices, vec![(0.0, 0.0)]);
+        assert_eq!(error("vertex-puzzle 3\n[vertices]\n0 0"), "line 1: unsupported puzzle header `vertex-puzzle 3`, expected `vertex-puzzle 2`");
+        assert_eq!(error("# new format\nvertex-puzzle\n"), "line 2: unsupported puzzle header `vertex-puzzle`, expected `vertex-puzzle 2`");
+    }
+
+    #[test]
+    fn sections_are_known_and_appear_once() {
+        assert_eq!(error("vertex-puzzle 2\n[vertices]\n0 0\n[vertices]\n1 1"), "line 4: section `[vertices]` appears more than once");
+        assert_eq!(error("vertex-puzzle 2\n[edges]\n0 1"), "line 2: unknown section `[edges]`");
+        assert_eq!(error("vertex-puzzle 2\n0 0\n[vertices]"), "line 2: `0 0` is not inside a section");
+    }
+
+    #[test]
+    fn z_coordinates_and_alpha_are_optional() {
+        let raw = parse(&mut "vertex-puzzle 2\n[vertices]\n0 0 5\n1 0\n0 1 -2.5\n[colors]\n255 0 0 51\n0 0 255\n[triangles]\n0 1 2 1\n".as_bytes()).unwrap();
+        assert_eq!(raw.vertices, vec![(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)]);
+        assert_eq!(raw.colors, vec![[1.0, 0.0, 0.0, 0.2], [0.0, 0.0, 1.0, 1.0]]);
+        assert_eq!(error("vertex-puzzle 2\n[vertices]\n0 0 z"), "line 3: invalid vertex: coordinate `z` is not a finite number");
+    }
+
+    #[test]
+    fn metadata_values_keep_hashes() {
+        let raw = parse(&mut "vertex-puzzle 2\n[meta]\n# about the puzzle\ntitle = Puzzle #1\npar = 5\n".as_bytes()).unwrap();
+        assert_eq!(raw.metadata.title.as_deref(), Some("Puzzle #1"));
+        assert_eq!(raw.metadata.par, Some(5));
+        assert_eq!(error("vertex-puzzle 2\n[meta]\npar = 5 # edges"), "line 3: invalid metadata: par `5 # edges` is not a non-negative integer");
+    }
+}
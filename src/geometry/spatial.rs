@@ -0,0 +1,109 @@
+// Uniform grid over the puzzle bounds for finding the vertex under the pointer without scanning them all.
+// Cells are sized so each holds about one vertex on average.
+#[derive(Debug)]
+pub struct VertexGrid {
+    lower: (f32, f32),
+    cell_size: f32,
+    dims: (usize, usize), // columns, rows
+    cells: Vec<Vec<u32>>,
+}
+
+impl VertexGrid {
+    pub fn new(vertices: &[(f32, f32)], lower: (f32, f32), upper: (f32, f32)) -> VertexGrid {
+        if vertices.is_empty() {
+            return VertexGrid { lower: (0., 0.), cell_size: 1., dims: (0, 0), cells: vec![] };
+        }
+
+        let extent = (upper.0 - lower.0, upper.1 - lower.1);
+        let area = (extent.0 * extent.1).max(extent.0 * extent.0).max(extent.1 * extent.1);
+        let cell_size = (area / vertices.len() as f32).sqrt().max(1e-6);
+        let dims = (
+            (extent.0 / cell_size).floor() as usize + 1,
+            (extent.1 / cell_size).floor() as usize + 1,
+        );
+
+        let mut out = VertexGrid { lower, cell_size, dims, cells: vec![vec![]; dims.0 * dims.1] };
+        for (idx, &v) in vertices.iter().enumerate() {
+            let (col, row) = out.cell_of(v);
+            out.cells[row * dims.0 + col].push(idx as u32);
+        }
+        out
+    }
+
+    fn cell_of(&self, point: (f32, f32)) -> (usize, usize) {
+        let clamp = |value: f32, lower: f32, count: usize| {
+            (((value - lower) / self.cell_size).floor().max(0.) as usize).min(count - 1)
+        };
+        (clamp(point.0, self.lower.0, self.dims.0), clamp(point.1, self.lower.1, self.dims.1))
+    }
+
+    // Closest vertex within `threshold` of `point` that `accept` allows, preferring lower indices on ties
+    pub fn nearest<F: Fn(u32) -> bool>(
+        &self,
+        vertices: &[(f32, f32)],
+        point: (f32, f32),
+        threshold: f32,
+        accept: F,
+    ) -> Option<u32> {
+        if self.cells.is_empty() { return None }
+
+        let (min_col, min_row) = self.cell_of((point.0 - threshold, point.1 - threshold));
+        let (max_col, max_row) = self.cell_of((point.0 + threshold, point.1 + threshold));
+
+        let mut best: Option<(f32, u32)> = None;
+        for row in min_row..=max_row {
+            for col in min_col..=max_col {
+                for &idx in &self.cells[row * self.dims.0 + col] {
+                    let v = vertices[idx as usize];
+                    let distance = (v.0 - point.0).hypot(v.1 - point.1);
+                    if distance > threshold { continue }
+                    let closer = match best {
+                        Some((best_distance, best_idx)) => (distance, idx) < (best_distance, best_idx),
+                        None => true,
+                    };
+                    if closer && accept(idx) { best = Some((distance, idx)); }
+                }
+            }
+        }
+        best.map(|(_, idx)| idx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_closest_vertex_rather_than_first() {
+        let vertices = vec![(0.0, 0.0), (1.0, 0.0), (0.1, 0.0), (0.12, 0.01)];
+        let grid = VertexGrid::new(&vertices, (0.0, 0.0), (1.0, 0.01));
+        assert_eq!(grid.nearest(&vertices, (0.11, 0.0), 0.12, |_| true), Some(2));
+        assert_eq!(grid.nearest(&vertices, (0.11, 0.0), 0.12, |v| v != 2), Some(3));
+        assert_eq!(grid.nearest(&vertices, (0.5, 0.5), 0.12, |_| true), None);
+    }
+
+    #[test]
+    fn matches_linear_scan() {
+        let vertices: Vec<(f32, f32)> = (0..400).map(|i| {
+            let i = i as f32;
+            ((i * 0.37).sin() * 3.0, (i * 0.73).cos() * 2.0)
+        }).collect();
+        let grid = VertexGrid::new(&vertices, (-3.0, -2.0), (3.0, 2.0));
+
+        for step in 0..200 {
+            let point = ((step as f32 * 0.11).cos() * 3.5, (step as f32 * 0.29).sin() * 2.5);
+            let expected = (0..vertices.len() as u32)
+                .map(|idx| {
+                    let v = vertices[idx as usize];
+                    ((v.0 - point.0).hypot(v.1 - point.1), idx)
+                })
+                .filter(|&(distance, _)| distance <= 0.3)
+                .fold(None, |best: Option<(f32, u32)>, candidate| match best {
+                    Some(b) if b <= candidate => Some(b),
+                    _ => Some(candidate),
+                })
+                .map(|(_, idx)| idx);
+            assert_eq!(grid.nearest(&vertices, point, 0.3, |_| true), expected);
+        }
+    }
+}
@@ -0,0 +1,285 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use super::{cross, PuzzleData};
+
+// Relative to the size of the puzzle, so validation works the same at any scale
+const AREA_EPSILON: f32 = 1e-6;
+const OVERLAP_EPSILON: f32 = 1e-5;
+
+// Renderers draw with 32-bit indices but GL takes the index count as an i32. Every triangle has
+// three indices and every vertex circle drawn without instancing has six.
+const MAX_TRIANGLES: usize = i32::MAX as usize / 3;
+const MAX_VERTICES: usize = i32::MAX as usize / 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning, // the puzzle is playable but probably not what the author intended
+    Error, // the puzzle can't be played correctly
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IssueKind {
+    VertexOutOfRange,
+    ColorOutOfRange,
+    RepeatedVertex,
+    DegenerateTriangle,
+    DuplicateTriangle,
+    OverlappingTriangles,
+    UnusedVertex,
+    UnusedColor,
+    TooManyVertices,
+    TooManyTriangles,
+}
+
+impl IssueKind {
+    pub fn severity(self) -> Severity {
+        match self {
+            IssueKind::UnusedVertex | IssueKind::UnusedColor => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Location {
+    Puzzle,
+    Vertex(u32),
+    Color(u32),
+    Triangle(u32),
+    TrianglePair(u32, u32),
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Location::Puzzle => write!(f, "puzzle"),
+            Location::Vertex(v) => write!(f, "vertex {}", v),
+            Location::Color(c) => write!(f, "color {}", c),
+            Location::Triangle(t) => write!(f, "triangle {}", t),
+            Location::TrianglePair(t0, t1) => write!(f, "triangles {} and {}", t0, t1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationIssue {
+    pub kind: IssueKind,
+    pub location: Location,
+    pub message: String,
+}
+
+impl ValidationIssue {
+    fn new(kind: IssueKind, location: Location, message: String) -> ValidationIssue {
+        ValidationIssue { kind, location, message }
+    }
+
+    pub fn severity(&self) -> Severity { self.kind.severity() }
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity() {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}: {}: {}", severity, self.location, self.message)
+    }
+}
+
+pub fn validate(data: &PuzzleData) -> Vec<ValidationIssue> {
+    let mut out = check_limits(data.vertices.len(), data.triangles.len());
+    let mut used_vertices: HashSet<u32> = HashSet::new();
+    let mut used_colors: HashSet<u32> = HashSet::new();
+    let mut seen_triangles: HashMap<[u32; 3], u32> = HashMap::new();
+    let mut duplicate_pairs: HashSet<(u32, u32)> = HashSet::new();
+
+    // Triangles that are sound enough to check against each other geometrically
+    let mut solid: Vec<u32> = vec![];
+
+    let extent = (data.upper_bounds.0 - data.lower_bounds.0).max(data.upper_bounds.1 - data.lower_bounds.1).max(0.0);
+
+    for (idx, triangle) in data.triangles.iter().enumerate() {
+        let idx = idx as u32;
+        let location = Location::Triangle(idx);
+        used_colors.insert(triangle[3]);
+        if triangle[3] as usize >= data.colors.len() {
+            out.push(ValidationIssue::new(IssueKind::ColorOutOfRange, location, format!(
+                "references color {} but only {} colors defined", triangle[3], data.colors.len()
+            )));
+        }
+
+        let out_of_range: Vec<u32> = triangle[0..3].iter().cloned().filter(|&v| v as usize >= data.vertices.len()).collect();
+        for &v in &out_of_range {
+            out.push(ValidationIssue::new(IssueKind::VertexOutOfRange, location, format!(
+                "references vertex {} but only {} vertices defined", v, data.vertices.len()
+            )));
+        }
+        used_vertices.extend(&triangle[0..3]);
+
+        let mut sorted = [triangle[0], triangle[1], triangle[2]];
+        sorted.sort();
+        if sorted[0] == sorted[1] || sorted[1] == sorted[2] {
+            out.push(ValidationIssue::new(IssueKind::RepeatedVertex, location, format!(
+                "uses the same vertex more than once in `{} {} {}`", triangle[0], triangle[1], triangle[2]
+            )));
+            continue;
+        }
+        if !out_of_range.is_empty() { continue }
+
+        if let Some(&first) = seen_triangles.get(&sorted) {
+            out.push(ValidationIssue::new(IssueKind::DuplicateTriangle, Location::TrianglePair(first, idx), format!(
+                "both use vertices {} {} {}", sorted[0], sorted[1], sorted[2]
+            )));
+            duplicate_pairs.insert((first, idx));
+        } else {
+            seen_triangles.insert(sorted, idx);
+        }
+
+        let corners = corners(data, idx);
+        let longest = (0..3).map(|i| distance_squared(corners[i], corners[(i + 1) % 3])).fold(0.0, f32::max);
+        if cross(corners[0], corners[1], corners[2]).abs() <= AREA_EPSILON * longest {
+            out.push(ValidationIssue::new(IssueKind::DegenerateTriangle, location, "has zero area".to_string()));
+            continue;
+        }
+
+        solid.push(idx);
+    }
+
+    for pair in overlapping_pairs(data, &solid, OVERLAP_EPSILON * extent) {
+        if duplicate_pairs.contains(&pair) { continue }
+        out.push(ValidationIssue::new(
+            IssueKind::OverlappingTriangles,
+            Location::TrianglePair(pair.0, pair.1),
+            "cover some of the same area".to_string(),
+        ));
+    }
+
+    for idx in 0..data.vertices.len() as u32 {
+        if !used_vertices.contains(&idx) {
+            out.push(ValidationIssue::new(IssueKind::UnusedVertex, Location::Vertex(idx), "is not part of any triangle".to_string()));
+        }
+    }
+
+    for idx in 0..data.colors.len() as u32 {
+        if !used_colors.contains(&idx) {
+            out.push(ValidationIssue::new(IssueKind::UnusedColor, Location::Color(idx), "is not used by any triangle".to_string()));
+        }
+    }
+
+    out
+}
+
+// Puzzles past these would draw garbage instead of failing, so they're refused up front
+fn check_limits(num_vertices: usize, num_triangles: usize) -> Vec<ValidationIssue> {
+    let mut out = vec![];
+    if num_vertices > MAX_VERTICES {
+        out.push(ValidationIssue::new(IssueKind::TooManyVertices, Location::Puzzle, format!(
+            "has {} vertices but at most {} can be drawn", num_vertices, MAX_VERTICES
+        )));
+    }
+    if num_triangles > MAX_TRIANGLES {
+        out.push(ValidationIssue::new(IssueKind::TooManyTriangles, Location::Puzzle, format!(
+            "has {} triangles but at most {} can be drawn", num_triangles, MAX_TRIANGLES
+        )));
+    }
+    out
+}
+
+fn corners(data: &PuzzleData, triangle: u32) -> [(f32, f32); 3] {
+    let t = &data.triangles[triangle as usize];
+    [data.vertices[t[0] as usize], data.vertices[t[1] as usize], data.vertices[t[2] as usize]]
+}
+
+fn distance_squared(a: (f32, f32), b: (f32, f32)) -> f32 {
+    (a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)
+}
+
+struct BoundedTriangle {
+    idx: u32,
+    corners: [(f32, f32); 3],
+    lower: (f32, f32),
+    upper: (f32, f32),
+}
+
+// Sweeps over triangles sorted by their left edge so only triangles with overlapping x ranges are compared
+fn overlapping_pairs(data: &PuzzleData, triangles: &[u32], tolerance: f32) -> Vec<(u32, u32)> {
+    let mut bounded: Vec<BoundedTriangle> = triangles.iter().map(|&idx| {
+        let c = corners(data, idx);
+        BoundedTriangle {
+            idx,
+            corners: c,
+            lower: (c[0].0.min(c[1].0).min(c[2].0), c[0].1.min(c[1].1).min(c[2].1)),
+            upper: (c[0].0.max(c[1].0).max(c[2].0), c[0].1.max(c[1].1).max(c[2].1)),
+        }
+    }).collect();
+    bounded.sort_by(|a, b| a.lower.0.partial_cmp(&b.lower.0).unwrap());
+
+    let mut out = vec![];
+    for (i, a) in bounded.iter().enumerate() {
+        for b in &bounded[i + 1..] {
+            if b.lower.0 >= a.upper.0 - tolerance { break }
+            if b.lower.1 >= a.upper.1 - tolerance || a.lower.1 >= b.upper.1 - tolerance { continue }
+            if interiors_intersect(&a.corners, &b.corners, tolerance) {
+                out.push((a.idx.min(b.idx), a.idx.max(b.idx)));
+            }
+        }
+    }
+    out.sort();
+    out
+}
+
+// Separating axis test where touching along an edge or at a corner doesn't count as overlapping
+fn interiors_intersect(a: &[(f32, f32); 3], b: &[(f32, f32); 3], tolerance: f32) -> bool {
+    for triangle in &[a, b] {
+        for i in 0..3 {
+            let (p, q) = (triangle[i], triangle[(i + 1) % 3]);
+            let length = distance_squared(p, q).sqrt();
+            let axis = ((q.1 - p.1) / length, (p.0 - q.0) / length);
+            let project = |points: &[(f32, f32); 3]| {
+                let dots: Vec<f32> = points.iter().map(|v| v.0 * axis.0 + v.1 * axis.1).collect();
+                (dots[0].min(dots[1]).min(dots[2]), dots[0].max(dots[1]).max(dots[2]))
+            };
+            let (a_min, a_max) = project(a);
+            let (b_min, b_max) = project(b);
+            if a_max - b_min <= tolerance || b_max - a_min <= tolerance { return false }
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(puzzle: &str) -> Vec<IssueKind> {
+        validate(&PuzzleData::parse(puzzle).unwrap()).iter().map(|i| i.kind).collect()
+    }
+
+    #[test]
+    fn bundled_puzzles_are_valid() {
+        for puzzle in &[include_str!("../../puzzles/1.txt"), include_str!("../../puzzles/2.txt"), include_str!("../../puzzles/3.txt")] {
+            assert_eq!(kinds(puzzle), vec![]);
+        }
+    }
+
+    #[test]
+    fn finds_structural_problems() {
+        assert_eq!(kinds("0 0\n1 0\n2 0\n255 0 0\n0 1 2 0"), vec![IssueKind::DegenerateTriangle]);
+        assert_eq!(kinds("0 0\n1 0\n0 1\n255 0 0\n0 1 2 0\n2 1 0 0"), vec![IssueKind::DuplicateTriangle]);
+        assert_eq!(kinds("0 0\n2 0\n0 2\n1 1\n255 0 0\n0 1 2 0\n0 1 3 0"), vec![IssueKind::OverlappingTriangles]);
+        assert_eq!(kinds("0 0\n1 0\n0 1\n5 5\n255 0 0\n0 255 0\n0 1 2 0"), vec![IssueKind::UnusedVertex, IssueKind::UnusedColor]);
+    }
+
+    #[test]
+    fn triangles_sharing_an_edge_do_not_overlap() {
+        assert_eq!(kinds("0 0\n1 0\n1 1\n0 1\n255 0 0\n0 1 2 0\n0 2 3 0"), vec![]);
+    }
+
+    #[test]
+    fn puzzles_too_big_to_draw_are_errors() {
+        assert_eq!(check_limits(MAX_VERTICES, MAX_TRIANGLES), vec![]);
+        let issues = check_limits(MAX_VERTICES + 1, MAX_TRIANGLES + 1);
+        assert_eq!(issues.iter().map(|i| i.kind).collect::<Vec<_>>(), vec![IssueKind::TooManyVertices, IssueKind::TooManyTriangles]);
+        assert!(issues.iter().all(|i| i.severity() == Severity::Error));
+    }
+}
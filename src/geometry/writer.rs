@@ -0,0 +1,53 @@
+use std::io::{Error, ErrorKind, Write};
+use super::PuzzleData;
+use super::parser::{HEADER, CURRENT_VERSION};
+
+// Writes the canonical form of a puzzle: the sectioned format with sections in a fixed order,
+// metadata keys in a fixed order, shortest round-tripping floats and alpha only for translucent colors.
+// Parsing the output gives back exactly the same puzzle.
+pub fn write_text<W: Write>(data: &PuzzleData, writer: &mut W) -> std::io::Result<()> {
+    writeln!(writer, "{} {}", HEADER, CURRENT_VERSION)?;
+
+    let metadata = &data.metadata;
+    let mut entries: Vec<(&str, String)> = vec![];
+    if let Some(title) = &metadata.title { entries.push(("title", title.clone())); }
+    if let Some(author) = &metadata.author { entries.push(("author", author.clone())); }
+    if let Some(difficulty) = &metadata.difficulty { entries.push(("difficulty", difficulty.clone())); }
+    if let Some(par) = metadata.par { entries.push(("par", par.to_string())); }
+
+    if !entries.is_empty() {
+        writeln!(writer, "[meta]")?;
+        for (key, value) in entries {
+            // These would come back as a new line or lose their edges, so the puzzle wouldn't round trip
+            if value.is_empty() || value.contains('\n') || value.contains('\r') || value.trim() != value {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("metadata `{}` can't be written to a text puzzle: {:?}", key, value),
+                ));
+            }
+            writeln!(writer, "{} = {}", key, value)?;
+        }
+    }
+
+    writeln!(writer, "[vertices]")?;
+    for (x, y) in &data.vertices {
+        writeln!(writer, "{} {}", x, y)?;
+    }
+
+    writeln!(writer, "[colors]")?;
+    for color in &data.colors {
+        let components: Vec<u8> = color.iter().map(|c| (c * 255.0).round() as u8).collect();
+        if components[3] == 255 {
+            writeln!(writer, "{} {} {}", components[0], components[1], components[2])?;
+        } else {
+            writeln!(writer, "{} {} {} {}", components[0], components[1], components[2], components[3])?;
+        }
+    }
+
+    writeln!(writer, "[triangles]")?;
+    for triangle in &data.triangles {
+        writeln!(writer, "{} {} {} {}", triangle[0], triangle[1], triangle[2], triangle[3])?;
+    }
+
+    Ok(())
+}
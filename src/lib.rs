@@ -4,13 +4,21 @@ mod geometry;
 mod puzzle_state;
 mod display;
 mod events;
+mod storage;
+mod solver;
 
 use std::rc::Rc;
 use std::cell::RefCell;
+use std::collections::VecDeque;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 use events::Event;
 
+pub use puzzle_state::{EdgeMode, PuzzleState, Rules, VertexStatus};
+pub use geometry::PuzzleData;
+pub use solver::{solve, Obstacle};
+pub use display::{renderer::Renderer, software::SoftwareRenderer};
+
 fn window() -> Result<web_sys::Window, JsValue> {
     web_sys::window().ok_or("No global window exists".into())
 }
@@ -21,24 +29,309 @@ fn get_canvas() -> Result<web_sys::HtmlCanvasElement, JsValue> {
     Ok(canvas.dyn_into::<web_sys::HtmlCanvasElement>()?)
 }
 
+// The canvas's size on screen in CSS pixels and how many device pixels each of those covers, which
+// changes with the window size, zoom, or moving the window to another display
+fn canvas_layout(canvas: &web_sys::HtmlCanvasElement) -> (i32, i32, f64) {
+    let pixel_ratio = web_sys::window().map(|w| w.device_pixel_ratio()).unwrap_or(1.0);
+    (canvas.client_width(), canvas.client_height(), pixel_ratio)
+}
+
+// Draw at the display's full resolution, whatever size CSS gives the canvas
+fn fit_canvas(canvas: &web_sys::HtmlCanvasElement, graphics: &mut dyn Renderer, layout: (i32, i32, f64)) {
+    let (width, height, pixel_ratio) = layout;
+    let width = ((width as f64 * pixel_ratio).round() as u32).max(1);
+    let height = ((height as f64 * pixel_ratio).round() as u32).max(1);
+    canvas.set_width(width);
+    canvas.set_height(height);
+    graphics.resize(width, height, pixel_ratio as f32);
+}
+
 fn request_animation_frame(f: &Closure<dyn FnMut()>) -> Result<i32, JsValue> {
     window()?.request_animation_frame(f.as_ref().unchecked_ref())
 }
 
+// Converts a puzzle in any supported format to the canonical text format
+#[wasm_bindgen]
+pub fn puzzle_to_text(puzzle: &str) -> Result<String, JsValue> {
+    let puzzle_data = geometry::PuzzleData::parse(puzzle).map_err(|e| e.to_string())?;
+    let mut out = vec![];
+    puzzle_data.write_to(&mut out).map_err(|e| e.to_string())?;
+    Ok(String::from_utf8(out).map_err(|e| e.to_string())?)
+}
+
+// Merges vertices closer than `tolerance`, drops unused data and sorts everything deterministically,
+// returning the result in the canonical text format
+#[wasm_bindgen]
+pub fn normalize_puzzle(puzzle: &str, tolerance: f32) -> Result<String, JsValue> {
+    let puzzle_data = geometry::PuzzleData::parse(puzzle).map_err(|e| e.to_string())?;
+    let mut out = vec![];
+    puzzle_data.normalized(tolerance).write_to(&mut out).map_err(|e| e.to_string())?;
+    Ok(String::from_utf8(out).map_err(|e| e.to_string())?)
+}
+
+// Converts a puzzle in any supported format to JSON
 #[wasm_bindgen]
-pub fn run(puzzle: &str) -> Result<(), JsValue> {
+pub fn puzzle_to_json(puzzle: &str) -> Result<String, JsValue> {
+    let puzzle_data = geometry::PuzzleData::parse(puzzle).map_err(|e| e.to_string())?;
+    Ok(puzzle_data.to_json())
+}
+
+// Converts a puzzle in any supported format to the compact binary format
+#[wasm_bindgen]
+pub fn puzzle_to_binary(puzzle: &str) -> Result<Vec<u8>, JsValue> {
+    let puzzle_data = geometry::PuzzleData::parse(puzzle).map_err(|e| e.to_string())?;
+    Ok(puzzle_data.to_binary().map_err(|e| e.to_string())?)
+}
+
+// Identity of a puzzle in any supported format as 16 hex digits, which doesn't change if the
+// puzzle is reordered or its metadata is edited
+#[wasm_bindgen]
+pub fn puzzle_fingerprint(puzzle: &str) -> Result<String, JsValue> {
+    let puzzle_data = geometry::PuzzleData::parse(puzzle).map_err(|e| e.to_string())?;
+    Ok(puzzle_state::puzzle_id(puzzle_data.fingerprint()))
+}
+
+// Everything about a running puzzle that both the animation loop and the JS handle need
+struct Session {
+    puzzle_data: geometry::PuzzleData,
+    puzzle_state: puzzle_state::PuzzleState,
+    started_at: f64, // when the puzzle clock read zero, in milliseconds since the epoch
+    playback: VecDeque<puzzle_state::TimedMove>, // replay moves that haven't been shown yet
+    autosave: bool,
+    saved_moves: usize, // length of the move log when progress was last stored
+    hint: Option<(puzzle_state::Hint, usize)>, // with the length of the move log when it was given
+    stopped: bool,
+}
+
+impl Session {
+    fn clock(&self) -> f64 { js_sys::Date::now() - self.started_at }
+
+    // Returns whether there was anything to suggest
+    fn show_hint(&mut self) -> bool {
+        let moves = self.puzzle_state.get_move_log().moves.len();
+        self.hint = self.puzzle_state.hint(&self.puzzle_data).map(|hint| (hint, moves));
+        self.hint.is_some()
+    }
+
+    // Hints only apply to the state they were given for, so any move clears them
+    fn current_hint(&self) -> Option<puzzle_state::Hint> {
+        let moves = self.puzzle_state.get_move_log().moves.len();
+        self.hint.filter(|&(_, given_at)| given_at == moves).map(|(hint, _)| hint)
+    }
+
+    fn puzzle_id(&self) -> String { puzzle_state::puzzle_id(self.puzzle_data.fingerprint()) }
+
+    fn restore(&mut self, save: &str) -> Result<(), JsValue> {
+        let saved = puzzle_state::SavedProgress::from_json(save).map_err(|e| e.to_string())?;
+        self.puzzle_state = puzzle_state::PuzzleState::from_save(&self.puzzle_data, &saved).map_err(|e| e.to_string())?;
+        self.playback.clear();
+        self.started_at = js_sys::Date::now();
+        // The restored edges are already in the log and already saved
+        self.saved_moves = self.puzzle_state.get_move_log().moves.len();
+        Ok(())
+    }
+
+    // Stores progress whenever a move was made since the last time. Replays aren't the player's own
+    // progress, so nothing is stored while one plays back.
+    fn autosave(&mut self) {
+        let moves = self.puzzle_state.get_move_log().moves.len();
+        if !self.autosave || !self.playback.is_empty() || moves == self.saved_moves { return }
+        self.saved_moves = moves;
+        let save = self.puzzle_state.save(&self.puzzle_data).to_json();
+        if let Err(e) = storage::store(&self.puzzle_id(), &save) {
+            web_sys::console::warn_2(&"Could not save progress:".into(), &e);
+        }
+    }
+}
+
+// Handle to a running puzzle, returned to JS by `run`
+#[wasm_bindgen]
+pub struct Game {
+    session: Rc<RefCell<Session>>,
+    listeners: Rc<RefCell<Vec<js_sys::Function>>>,
+}
+
+#[wasm_bindgen]
+impl Game {
+    // Calls `callback` with an object like `{type: "triangle_unlocked", triangle: 3}` for every
+    // game event, see puzzle_state/game_event.rs for the full list
+    pub fn on_event(&self, callback: js_sys::Function) {
+        self.listeners.borrow_mut().push(callback);
+    }
+
+    // Highlights a useful next edge, or a wrong one to remove, until the next move.
+    // Returns false if the puzzle is already finished.
+    pub fn show_hint(&self) -> bool { self.session.borrow_mut().show_hint() }
+
+    // Bulk moves, each undoable as a single step. They return false if nothing changed.
+    pub fn reset(&self) -> bool { self.mutate(|state, data| state.reset(data)) }
+    pub fn clear_mistakes(&self) -> bool { self.mutate(|state, data| state.clear_mistakes(data)) }
+    // Shows the solution, for debugging and giving up
+    pub fn reveal_all(&self) -> bool { self.mutate(|state, data| state.reveal_all(data)) }
+
+    pub fn puzzle_id(&self) -> String { self.session.borrow().puzzle_id() }
+    pub fn is_finished(&self) -> bool { self.session.borrow().puzzle_state.is_finished() }
+    // Every wrong edge ever drawn, including ones that were removed again
+    pub fn mistakes(&self) -> usize { self.session.borrow().puzzle_state.get_total_mistakes() }
+    // Wrong edges that are connected right now
+    pub fn wrong_edges(&self) -> usize { self.session.borrow().puzzle_state.get_wrong_edges() }
+
+    // Identifies which edges are connected right now, as 16 hex digits
+    pub fn progress_id(&self) -> String {
+        let session = self.session.borrow();
+        puzzle_state::puzzle_id(session.puzzle_state.fingerprint(&session.puzzle_data))
+    }
+
+    // Every move made so far as JSON, for bug reports or replaying later
+    pub fn move_log(&self) -> String {
+        self.session.borrow().puzzle_state.get_move_log().to_json()
+    }
+
+    // Jumps straight to the state a move log ends in
+    pub fn load_replay(&self, log: &str) -> Result<(), JsValue> {
+        let mut session = self.session.borrow_mut();
+        let log = puzzle_state::MoveLog::from_json(log).map_err(|e| e.to_string())?;
+        session.puzzle_state = puzzle_state::PuzzleState::replay(&session.puzzle_data, &log).map_err(|e| e.to_string())?;
+        session.playback.clear();
+        session.started_at = js_sys::Date::now() - log.moves.last().map(|m| m.time).unwrap_or(0.0);
+        // Only stored once the player moves on from the replayed state
+        session.saved_moves = log.moves.len();
+        Ok(())
+    }
+
+    // Current progress as JSON, which `load` accepts for the same puzzle
+    pub fn save(&self) -> String {
+        let session = self.session.borrow();
+        session.puzzle_state.save(&session.puzzle_data).to_json()
+    }
+
+    // Replaces the current progress, failing if the save is for a different puzzle
+    pub fn load(&self, save: &str) -> Result<(), JsValue> {
+        self.session.borrow_mut().restore(save)
+    }
+
+    // Keeps progress in localStorage after every move
+    pub fn set_autosave(&self, enabled: bool) {
+        self.session.borrow_mut().autosave = enabled;
+    }
+
+    // Loads progress from localStorage, returning whether there was any for this puzzle
+    pub fn restore_saved(&self) -> Result<bool, JsValue> {
+        let mut session = self.session.borrow_mut();
+        match storage::load(&session.puzzle_id())? {
+            Some(save) => session.restore(&save).map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    pub fn clear_saved(&self) -> Result<(), JsValue> {
+        storage::clear(&self.session.borrow().puzzle_id())
+    }
+
+    // Ends the game loop and releases its GPU resources and input listeners. The handle stays usable for saving.
+    pub fn stop(&self) {
+        self.session.borrow_mut().stopped = true;
+    }
+
+    // Starts the puzzle over and plays a move log back at the speed it was recorded.
+    // Player input is ignored until playback finishes.
+    pub fn watch_replay(&self, log: &str) -> Result<(), JsValue> {
+        let mut session = self.session.borrow_mut();
+        let log = puzzle_state::MoveLog::from_json(log).map_err(|e| e.to_string())?;
+        puzzle_state::PuzzleState::replay(&session.puzzle_data, &log).map_err(|e| e.to_string())?;
+        session.puzzle_state = puzzle_state::PuzzleState::from_data(
+            &session.puzzle_data,
+            puzzle_state::Rules { edge_mode: log.edge_mode },
+        );
+        // Only stored once the player moves on from where the replay ends
+        session.saved_moves = log.moves.len();
+        session.playback = log.moves.into_iter().collect();
+        session.started_at = js_sys::Date::now();
+        Ok(())
+    }
+}
+
+impl Game {
+    fn mutate<F: FnOnce(&mut puzzle_state::PuzzleState, &geometry::PuzzleData) -> bool>(&self, f: F) -> bool {
+        let mut session = self.session.borrow_mut();
+        let clock = session.clock();
+        let Session { puzzle_data, puzzle_state, playback, .. } = &mut *session;
+        // Moves made while a replay plays back would be lost in it
+        if !playback.is_empty() { return false }
+        puzzle_state.set_clock(clock);
+        f(puzzle_state, puzzle_data)
+    }
+}
+
+// Accepts either a text or JSON puzzle
+#[wasm_bindgen]
+pub fn run(puzzle: &str, edge_mode: EdgeMode) -> Result<Game, JsValue> {
+    start(geometry::PuzzleData::parse(puzzle).map_err(|e| e.to_string())?, edge_mode)
+}
+
+#[wasm_bindgen]
+pub fn run_binary(puzzle: &[u8], edge_mode: EdgeMode) -> Result<Game, JsValue> {
+    start(geometry::PuzzleData::from_binary(puzzle).map_err(|e| e.to_string())?, edge_mode)
+}
+
+fn dispatch(listeners: &[js_sys::Function], game_events: Vec<puzzle_state::GameEvent>) {
+    for event in game_events {
+        let value = match js_sys::JSON::parse(&event.to_json()) {
+            Ok(value) => value,
+            Err(e) => { web_sys::console::error_1(&e); continue },
+        };
+        for listener in listeners {
+            if let Err(e) = listener.call1(&JsValue::NULL, &value) {
+                web_sys::console::error_2(&"Game event listener failed:".into(), &e);
+            }
+        }
+    }
+}
+
+fn start(puzzle_data: geometry::PuzzleData, edge_mode: EdgeMode) -> Result<Game, JsValue> {
+    // Refuse to start broken puzzles, but let ones that are merely untidy through
+    let issues = puzzle_data.validate();
+    let errors: Vec<String> = issues.iter()
+        .filter(|issue| issue.severity() == geometry::Severity::Error)
+        .map(|issue| issue.to_string())
+        .collect();
+    if !errors.is_empty() { return Err(errors.join("\n").into()) }
+    for issue in issues {
+        web_sys::console::warn_1(&issue.to_string().into());
+    }
+
     // Set up main components of the game
-    let puzzle_data = geometry::PuzzleData::from_reader(&mut puzzle.as_bytes()).map_err(|e| e.to_string())?;
-    let mut puzzle_state = puzzle_state::PuzzleState::from_data(&puzzle_data);
-    let mut graphics = display::graphics::Graphics::from_canvas(&get_canvas()?).map_err(|e| e.to_string())?;
-    let event_handler = events::EventHandler::init_from_canvas(&get_canvas()?)?;
+    let rules = puzzle_state::Rules { edge_mode };
+    let puzzle_state = puzzle_state::PuzzleState::from_data(&puzzle_data, rules);
+    let canvas = get_canvas()?;
+    let mut graphics = display::renderer_for_canvas(&canvas).map_err(|e| format!("{:?}", e))?;
+    let event_handler = events::EventHandler::init_from_canvas(&canvas)?;
+
+    // Show the puzzle's title in the tab if it has one
+    if let Some(title) = &puzzle_data.get_metadata().title {
+        window()?.document().ok_or("Could not get document")?.set_title(&format!("Vertex - {}", title));
+    }
 
     // Frame puzzle with even padding on all sides in window
     graphics.set_bounds(puzzle_data.get_lower_bounds(), puzzle_data.get_upper_bounds());
+    let mut layout = canvas_layout(&canvas);
+    fit_canvas(&canvas, &mut *graphics, layout);
+
+    // Static geometry goes to the GPU once, dynamic geometry is rebuilt every frame
+    graphics.set_static_data(&puzzle_data.get_static_graphics_data());
 
-    // Set up static and dynamic geometry
-    let static_geometry = puzzle_data.get_static_graphics_data();
-    let mut dynamic_geometry = puzzle_data.get_dynamic_graphics_data(&puzzle_state, &None, &None);
+    let session = Rc::new(RefCell::new(Session {
+        puzzle_data,
+        puzzle_state,
+        started_at: js_sys::Date::now(),
+        playback: VecDeque::new(),
+        autosave: false,
+        saved_moves: 0,
+        hint: None,
+        stopped: false,
+    }));
+    let listeners = Rc::new(RefCell::new(vec![]));
+    let game = Game { session: session.clone(), listeners: listeners.clone() };
 
     let mut last_vertex_clicked: Option<u32> = None;
     let mut curr_pointer_position: Option<(f32, f32)> = None;
@@ -47,28 +340,63 @@ pub fn run(puzzle: &str) -> Result<(), JsValue> {
     // callback to reference itself (to request the next frame)
     let f = Rc::new(RefCell::new(None));
     let g = f.clone();
+    let mut graphics = Some(graphics);
     *g.borrow_mut() = Some(Closure::wrap(Box::new(move || {
-        if !puzzle_state.is_finished() {
+        let mut session = session.borrow_mut();
+        // Stopping frees everything on the GPU, stops listening for input and ends the loop by not asking
+        // for another frame. Taking this closure out of `f` breaks the cycle through it, and wasm-bindgen
+        // waits until it returns before freeing it.
+        if session.stopped {
+            graphics = None;
+            event_handler.borrow_mut().detach();
+            let _ = f.borrow_mut().take();
+            return
+        }
+        let graphics = graphics.as_mut().unwrap();
+        // Checked every frame since not everything that resizes the canvas fires an event
+        if canvas_layout(&canvas) != layout {
+            layout = canvas_layout(&canvas);
+            fit_canvas(&canvas, &mut **graphics, layout);
+        }
+        let clock = session.clock();
+        let mut hint_requested = false;
+        let Session { puzzle_data, puzzle_state, playback, .. } = &mut *session;
+        puzzle_state.set_clock(clock);
+
+        if !playback.is_empty() {
+            while playback.front().map(|m| m.time <= clock).unwrap_or(false) {
+                let m = playback.pop_front().unwrap();
+                puzzle_state.apply_move(puzzle_data, &m);
+            }
+            // Player input made during playback is dropped
+            if let Ok(mut h) = event_handler.try_borrow_mut() { let _ = h.pending(); }
+            last_vertex_clicked = None;
+            curr_pointer_position = None;
+        } else if !puzzle_state.is_finished() {
             if let Ok(mut h) = event_handler.try_borrow_mut() {
                 for event in h.pending() {
                     match event {
                         Event::MouseDown(x, y) => {
                             last_vertex_clicked = puzzle_data.get_vertex_near(
-                                &puzzle_state,
+                                puzzle_state,
                                 graphics.unproject(x, y),
-                                0.12
+                                geometry::VERTEX_SELECT_RADIUS,
                             );
                         },
                         Event::MouseMove(x, y) => {
                             curr_pointer_position = Some(graphics.unproject(x, y));
                         },
                         Event::MouseUp(x, y) => {
-                            let maybe_v2 = puzzle_data.get_vertex_near(&puzzle_state, graphics.unproject(x, y), 0.12);
+                            let maybe_v2 = puzzle_data.get_vertex_near(
+                                puzzle_state,
+                                graphics.unproject(x, y),
+                                geometry::VERTEX_SELECT_RADIUS,
+                            );
                             if let (Some(v1), Some(v2)) = (last_vertex_clicked.take(), maybe_v2) {
                                 if v1 == v2 {
-                                    puzzle_state.disconnect_from_vertex(&puzzle_data, v1);
+                                    puzzle_state.disconnect_from_vertex(puzzle_data, v1);
                                 } else {
-                                    puzzle_state.connect_edge(&puzzle_data, &(v1, v2));
+                                    puzzle_state.connect_edge(puzzle_data, &(v1, v2));
                                 }
                             } else {
                                 last_vertex_clicked = None;
@@ -78,22 +406,63 @@ pub fn run(puzzle: &str) -> Result<(), JsValue> {
                             last_vertex_clicked = None;
                             curr_pointer_position = None;
                         },
+                        Event::Undo => {
+                            last_vertex_clicked = None;
+                            puzzle_state.undo(puzzle_data);
+                        },
+                        Event::Redo => {
+                            last_vertex_clicked = None;
+                            puzzle_state.redo(puzzle_data);
+                        },
+                        Event::Hint => hint_requested = true,
+                        Event::Reset => {
+                            last_vertex_clicked = None;
+                            puzzle_state.reset(puzzle_data);
+                        },
+                        Event::ClearMistakes => {
+                            last_vertex_clicked = None;
+                            puzzle_state.clear_mistakes(puzzle_data);
+                        },
+                        Event::RevealAll => {
+                            last_vertex_clicked = None;
+                            puzzle_state.reveal_all(puzzle_data);
+                        },
                     }
                 }
             }
         } else {
             last_vertex_clicked = None;
             curr_pointer_position = None;
+            // Starting over is the only thing left to do with a finished puzzle
+            if let Ok(mut h) = event_handler.try_borrow_mut() {
+                if h.pending().any(|event| matches!(event, Event::Reset)) { puzzle_state.reset(puzzle_data); }
+            }
         }
 
-        dynamic_geometry = puzzle_data.get_dynamic_graphics_data(
-            &puzzle_state,
+        // Saved as soon as the moves are made, so closing the tab doesn't lose the last one
+        session.autosave();
+
+        if hint_requested { session.show_hint(); }
+        let hint = session.current_hint();
+        let dynamic_geometry = session.puzzle_data.get_dynamic_graphics_data(
+            &session.puzzle_state,
             &last_vertex_clicked,
             &curr_pointer_position,
+            &hint,
         );
-        graphics.draw(&static_geometry, &dynamic_geometry);
+        graphics.draw(&dynamic_geometry, clock);
+
+        // Listeners may call back into the game, even to add more listeners, so they only run once the
+        // session is released and from a copy of the list
+        let game_events = session.puzzle_state.drain_events();
+        drop(session);
+        if !game_events.is_empty() {
+            let current = listeners.borrow().clone();
+            dispatch(&current, game_events);
+        }
+
         request_animation_frame(f.borrow().as_ref().unwrap()).unwrap();
     }) as Box<dyn FnMut()>));
     request_animation_frame(g.borrow().as_ref().unwrap())?;
-    Ok(())
+    Ok(game)
 }
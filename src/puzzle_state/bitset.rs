@@ -0,0 +1,69 @@
+// Fixed-size set of small integers, one bit each, for state that's indexed by edge, triangle or
+// vertex id. Keeps its own count so that `len` doesn't have to walk the words.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BitSet {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl BitSet {
+    pub fn with_capacity(bits: usize) -> BitSet {
+        BitSet { words: vec![0; bits.div_ceil(64)], len: 0 }
+    }
+
+    // Returns whether the bit was newly set
+    pub fn insert(&mut self, bit: u32) -> bool {
+        let (word, mask) = (bit as usize / 64, 1u64 << (bit % 64));
+        if self.words[word] & mask != 0 { return false }
+        self.words[word] |= mask;
+        self.len += 1;
+        true
+    }
+
+    // Returns whether the bit was set
+    pub fn remove(&mut self, bit: u32) -> bool {
+        let (word, mask) = (bit as usize / 64, 1u64 << (bit % 64));
+        if self.words[word] & mask == 0 { return false }
+        self.words[word] &= !mask;
+        self.len -= 1;
+        true
+    }
+
+    pub fn contains(&self, bit: u32) -> bool {
+        self.words.get(bit as usize / 64).map(|w| w & (1u64 << (bit % 64)) != 0).unwrap_or(false)
+    }
+
+    pub fn len(&self) -> usize { self.len }
+
+    // Set bits in increasing order
+    pub fn iter(&self) -> impl Iterator<Item = u32> + '_ {
+        self.words.iter().enumerate().flat_map(|(idx, &word)| {
+            let mut rest = word;
+            std::iter::from_fn(move || {
+                if rest == 0 { return None }
+                let bit = rest.trailing_zeros();
+                rest &= rest - 1;
+                Some(idx as u32 * 64 + bit)
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracks_bits_across_words() {
+        let mut set = BitSet::with_capacity(130);
+        assert!(set.insert(129) && set.insert(3) && set.insert(64));
+        assert!(!set.insert(64));
+        assert_eq!(set.iter().collect::<Vec<u32>>(), vec![3, 64, 129]);
+        assert_eq!(set.len(), 3);
+
+        assert!(set.remove(3));
+        assert!(!set.remove(3));
+        assert!(!set.contains(3) && set.contains(129) && !set.contains(500));
+        assert_eq!(set.len(), 2);
+    }
+}
@@ -0,0 +1,20 @@
+use serde::Serialize;
+
+// Something that happened to the puzzle as a result of a move, for driving sounds and UI.
+// Vertices and triangles are indices into the puzzle as loaded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum GameEvent {
+    TriangleUnlocked { triangle: usize }, // all three edges are connected so the triangle is revealed
+    TriangleRelocked { triangle: usize }, // undo took away an edge of a revealed triangle
+    VertexCompleted { vertex: u32 }, // every edge from the vertex is permanent
+    EdgeRejected { from: u32, to: u32 }, // strict mode refused an edge that isn't part of the puzzle
+    PuzzleFinished { mistakes: usize },
+}
+
+impl GameEvent {
+    // `{"type": "triangle_unlocked", "triangle": 3}` and so on
+    pub fn to_json(self) -> String {
+        serde_json::to_string(&self).unwrap()
+    }
+}
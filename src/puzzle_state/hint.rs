@@ -0,0 +1,41 @@
+use std::cmp::Reverse;
+use super::PuzzleState;
+use super::super::geometry;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hint {
+    Remove((u32, u32)), // a connected edge that isn't part of the puzzle
+    Connect((u32, u32)),
+}
+
+impl Hint {
+    pub fn edge(self) -> (u32, u32) {
+        match self {
+            Hint::Remove(edge) | Hint::Connect(edge) => edge,
+        }
+    }
+}
+
+// Mistakes get pointed out before anything else. Otherwise the best edge to draw is one that reveals
+// the most triangles right away, then one at a vertex that's nearly done, since those are the
+// easiest for the player to confirm by looking at the picture.
+pub fn suggest(data: &geometry::PuzzleData, state: &PuzzleState) -> Option<Hint> {
+    if let Some(&edge) = state.mistake_edges.iter().min() {
+        return Some(Hint::Remove(edge));
+    }
+
+    // Puzzle edges at the vertex that aren't connected yet
+    let remaining = |v: u32| {
+        let status = state.vertex_status(data, v);
+        status.required.saturating_sub(status.connected.saturating_sub(status.surplus))
+    };
+
+    (0..data.num_edges() as u32)
+        .filter(|&id| !state.connected_edges.contains(id))
+        .max_by_key(|&id| {
+            let edge = data.get_edge(id);
+            let completes = data.triangles_with_edge(id).iter().filter(|&&t| state.triangle_reqs[t as usize] == 1).count();
+            (completes, Reverse(remaining(edge.0).min(remaining(edge.1))), Reverse(edge))
+        })
+        .map(|id| Hint::Connect(data.get_edge(id)))
+}
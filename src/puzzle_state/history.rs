@@ -0,0 +1,48 @@
+use std::collections::VecDeque;
+
+// Oldest steps are forgotten beyond this
+const HISTORY_LIMIT: usize = 500;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EdgeChange {
+    Connected((u32, u32)),
+    Disconnected((u32, u32)),
+}
+
+impl EdgeChange {
+    pub fn inverse(self) -> EdgeChange {
+        match self {
+            EdgeChange::Connected(edge) => EdgeChange::Disconnected(edge),
+            EdgeChange::Disconnected(edge) => EdgeChange::Connected(edge),
+        }
+    }
+}
+
+// Undo and redo stacks where each step is every edge change made by a single player action
+#[derive(Clone)]
+pub struct History {
+    undo_steps: VecDeque<Vec<EdgeChange>>,
+    redo_steps: Vec<Vec<EdgeChange>>,
+}
+
+impl History {
+    pub fn new() -> History {
+        History { undo_steps: VecDeque::new(), redo_steps: vec![] }
+    }
+
+    // A new player action makes anything that was undone unreachable
+    pub fn record(&mut self, step: Vec<EdgeChange>) {
+        if step.is_empty() { return }
+        self.redo_steps.clear();
+        self.push_undo(step);
+    }
+
+    pub fn push_undo(&mut self, step: Vec<EdgeChange>) {
+        self.undo_steps.push_back(step);
+        if self.undo_steps.len() > HISTORY_LIMIT { self.undo_steps.pop_front(); }
+    }
+
+    pub fn push_redo(&mut self, step: Vec<EdgeChange>) { self.redo_steps.push(step); }
+    pub fn take_undo(&mut self) -> Option<Vec<EdgeChange>> { self.undo_steps.pop_back() }
+    pub fn take_redo(&mut self) -> Option<Vec<EdgeChange>> { self.redo_steps.pop() }
+}
@@ -1,101 +1,653 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::BTreeSet;
 use super::geometry;
 
+mod rules;
+mod history;
+mod replay;
+mod save;
+mod game_event;
+mod hint;
+mod vertex_status;
+mod bitset;
+
+pub use rules::{EdgeMode, Rules};
+pub use replay::{Move, MoveLog, ReplayError, TimedMove};
+pub use save::{puzzle_id, SavedProgress, SaveError};
+pub use game_event::GameEvent;
+pub use hint::Hint;
+pub use vertex_status::VertexStatus;
+use history::{EdgeChange, History};
+use bitset::BitSet;
+
+#[derive(Clone)]
 pub struct PuzzleState {
-    triangle_reqs: Vec<u32>,
-    unlocked_triangles: HashSet<usize>,
-    connected_edges: HashSet<(u32, u32)>, // v0, v1 sorted
-    connected_edges_by_vertex: HashMap<u32, HashSet<(u32, u32)>>,
-    permanent_edges_by_vertex: HashMap<u32, HashSet<(u32, u32)>>,
-    permanent_edges: HashSet<(u32, u32)>,
-    permanent_vertices: HashSet<u32>,
+    rules: Rules,
+    // Puzzle edges, triangles and vertices are tracked by their ids, see geometry/edges.rs
+    triangle_reqs: Vec<u8>, // edges each triangle still needs
+    unlocked_triangles: BitSet,
+    connected_edges: BitSet,
+    permanent_edges: BitSet,
+    permanent_vertices: BitSet,
+    mistake_edges: BTreeSet<(u32, u32)>, // connected edges that aren't part of the puzzle, v0, v1 sorted
+    connected_by_vertex: Vec<u32>, // connected edges at each vertex, right or wrong
+    permanent_by_vertex: Vec<u32>,
+    mistakes_by_vertex: Vec<u32>,
+    total_mistakes: usize, // every non-puzzle edge ever drawn, even if it was removed again
+    history: History,
+    clock: f64, // milliseconds since the puzzle was started, set by the game
+    move_log: MoveLog,
+    events: Vec<GameEvent>, // emitted since the game last drained them
 }
 
 impl PuzzleState {
-    pub fn from_data(data: &geometry::PuzzleData) -> PuzzleState {
+    pub fn from_data(data: &geometry::PuzzleData, rules: Rules) -> PuzzleState {
         PuzzleState {
+            rules,
             triangle_reqs: vec![3; data.num_triangles()],
-            unlocked_triangles: HashSet::new(),
-            connected_edges: HashSet::new(),
-            connected_edges_by_vertex: HashMap::new(),
-            permanent_edges_by_vertex: HashMap::new(),
-            permanent_edges: HashSet::new(),
-            permanent_vertices: HashSet::new(),
+            unlocked_triangles: BitSet::with_capacity(data.num_triangles()),
+            connected_edges: BitSet::with_capacity(data.num_edges()),
+            permanent_edges: BitSet::with_capacity(data.num_edges()),
+            permanent_vertices: BitSet::with_capacity(data.num_vertices()),
+            mistake_edges: BTreeSet::new(),
+            connected_by_vertex: vec![0; data.num_vertices()],
+            permanent_by_vertex: vec![0; data.num_vertices()],
+            mistakes_by_vertex: vec![0; data.num_vertices()],
+            total_mistakes: 0,
+            history: History::new(),
+            clock: 0.0,
+            move_log: MoveLog::new(puzzle_id(data.fingerprint()), rules.edge_mode),
+            events: vec![],
+        }
+    }
+
+    // Rebuilds the state a move log ends in, failing if any move doesn't apply to this puzzle
+    pub fn replay(data: &geometry::PuzzleData, log: &MoveLog) -> Result<PuzzleState, ReplayError> {
+        let expected = puzzle_id(data.fingerprint());
+        if log.puzzle != expected {
+            return Err(ReplayError::WrongPuzzle { expected, found: log.puzzle.clone() });
+        }
+
+        let mut out = PuzzleState::from_data(data, Rules { edge_mode: log.edge_mode });
+        for (idx, m) in log.moves.iter().enumerate() {
+            if !out.apply_move(data, m) {
+                return Err(ReplayError::IllegalMove { index: idx, reason: format!("{:?} changes nothing", m.action) });
+            }
+        }
+        // Everything in the log already happened, so none of it should be announced again
+        out.events.clear();
+        Ok(out)
+    }
+
+    pub fn save(&self, data: &geometry::PuzzleData) -> SavedProgress {
+        let edges = self.connected_edges(data).map(|e| {
+            let ids = (data.canonical_vertex_id(e.0), data.canonical_vertex_id(e.1));
+            (ids.0.min(ids.1), ids.0.max(ids.1))
+        });
+        SavedProgress::new(data.fingerprint(), self.rules.edge_mode, self.total_mistakes, edges.collect())
+    }
+
+    // Identifies the current progress, for comparing states without comparing every edge
+    pub fn fingerprint(&self, data: &geometry::PuzzleData) -> u64 {
+        data.progress_fingerprint(self.connected_edges(data))
+    }
+
+    // Restores saved progress, refusing saves made for a different puzzle or that break the rules
+    pub fn from_save(data: &geometry::PuzzleData, saved: &SavedProgress) -> Result<PuzzleState, SaveError> {
+        let expected = puzzle_id(data.fingerprint());
+        if saved.puzzle != expected {
+            return Err(SaveError::WrongPuzzle { expected, found: saved.puzzle.clone() });
         }
+
+        // The move log starts with the saved edges so that replaying it still ends in the current state.
+        // They're connected in canonical order at time 0, like a player who drew them all at once.
+        let mut out = PuzzleState::from_data(data, Rules { edge_mode: saved.edge_mode });
+        let mut edges = saved.edges.clone();
+        edges.sort();
+        for &ids in &edges {
+            let invalid = |reason: &str| SaveError::InvalidEdge { edge: ids, reason: reason.to_string() };
+            let edge = match (data.vertex_from_canonical_id(ids.0), data.vertex_from_canonical_id(ids.1)) {
+                (Some(v0), Some(v1)) if v0 != v1 => (v0.min(v1), v0.max(v1)),
+                _ => return Err(invalid("not an edge between two vertices")),
+            };
+            let in_puzzle = data.get_edge_id(&edge).is_some();
+            if !in_puzzle && saved.edge_mode == EdgeMode::Strict { return Err(invalid("not part of the puzzle")) }
+            if !out.add_edge(data, edge) { return Err(invalid("saved more than once")) }
+            out.log_move(Move::Connect { from: ids.0, to: ids.1 });
+        }
+        out.total_mistakes = saved.mistakes.max(out.mistake_edges.len());
+        // Restoring isn't playing, so nothing should be announced
+        out.events.clear();
+        Ok(out)
+    }
+
+    // Returns whether the move changed anything, in which case it's logged like any other
+    pub fn apply_move(&mut self, data: &geometry::PuzzleData, m: &TimedMove) -> bool {
+        self.set_clock(m.time);
+        match m.action {
+            Move::Connect { from, to } => match (data.vertex_from_canonical_id(from), data.vertex_from_canonical_id(to)) {
+                (Some(v0), Some(v1)) => v0 != v1 && self.connect_edge(data, &(v0, v1)),
+                _ => false,
+            },
+            Move::DisconnectVertex { vertex } => match data.vertex_from_canonical_id(vertex) {
+                Some(v) => self.disconnect_from_vertex(data, v),
+                None => false,
+            },
+            Move::Reset => self.reset(data),
+            Move::ClearMistakes => self.clear_mistakes(data),
+            Move::RevealAll => self.reveal_all(data),
+            Move::Undo => self.undo(data),
+            Move::Redo => self.redo(data),
+        }
+    }
+
+    pub fn set_clock(&mut self, time: f64) { self.clock = time; }
+    pub fn drain_events(&mut self) -> Vec<GameEvent> { std::mem::take(&mut self.events) }
+    pub fn get_move_log(&self) -> &MoveLog { &self.move_log }
+
+    fn log_move(&mut self, action: Move) {
+        self.move_log.moves.push(TimedMove { time: self.clock, action });
     }
 
-    pub fn connect_edge(&mut self, data: &geometry::PuzzleData, edge: &(u32, u32)) {
+    // Returns whether the edge was newly connected
+    pub fn connect_edge(&mut self, data: &geometry::PuzzleData, edge: &(u32, u32)) -> bool {
         let edge_ordered = if edge.0 > edge.1 { (edge.1, edge.0) } else { *edge };
-        if !self.connected_edges.insert(edge_ordered) { return }
-
-        self.connected_edges_by_vertex.entry(edge.0).or_insert(HashSet::new()).insert(edge_ordered);
-        self.connected_edges_by_vertex.entry(edge.1).or_insert(HashSet::new()).insert(edge_ordered);
-
-        if let Some(triangles_with_edge) = data.triangles_with_edge(&edge_ordered) {
-            for &triangle in triangles_with_edge {
-                self.triangle_reqs[triangle] -= 1;
-                if self.triangle_reqs[triangle] == 0 {
-                    self.unlocked_triangles.insert(triangle);
-                    for e_perm in data.get_edges_for_triangle(triangle as u32) {
-                        self.permanent_edges_by_vertex.entry(e_perm.0).or_insert(HashSet::new()).insert(e_perm);
-                        self.permanent_edges_by_vertex.entry(e_perm.1).or_insert(HashSet::new()).insert(e_perm);
-                        self.permanent_edges.insert(e_perm);
-
-                        if self.permanent_edges_by_vertex[&e_perm.0].len() == data.num_edges_from_vertex(e_perm.0) {
-                            self.permanent_vertices.insert(e_perm.0);
-                        }
-                        if self.permanent_edges_by_vertex[&e_perm.1].len() == data.num_edges_from_vertex(e_perm.1) {
-                            self.permanent_vertices.insert(e_perm.1);
-                        }
+        let in_puzzle = data.get_edge_id(&edge_ordered).is_some();
+        if !in_puzzle && self.rules.edge_mode == EdgeMode::Strict {
+            self.events.push(GameEvent::EdgeRejected { from: edge.0, to: edge.1 });
+            return false
+        }
+        if !self.add_edge(data, edge_ordered) { return false }
+
+        if !in_puzzle { self.total_mistakes += 1; }
+        self.history.record(vec![EdgeChange::Connected(edge_ordered)]);
+        self.log_move(Move::Connect { from: data.canonical_vertex_id(edge.0), to: data.canonical_vertex_id(edge.1) });
+        true
+    }
+
+    // Removes every non-permanent edge from a vertex as a single undoable step
+    pub fn disconnect_from_vertex(&mut self, data: &geometry::PuzzleData, vertex: u32) -> bool {
+        if self.vertex_status(data, vertex).is_done() { return false }
+        let removable = data.edges_from_vertex(vertex).iter()
+            .filter(|&&id| self.connected_edges.contains(id) && !self.permanent_edges.contains(id))
+            .map(|&id| data.get_edge(id));
+        let wrong = self.mistake_edges.iter().filter(|e| e.0 == vertex || e.1 == vertex).cloned();
+        let mut edges: Vec<(u32, u32)> = removable.chain(wrong).collect();
+        edges.sort();
+        self.change_edges(data, edges, vec![], Move::DisconnectVertex { vertex: data.canonical_vertex_id(vertex) })
+    }
+
+    // Disconnects every edge, including those of revealed triangles, as a single undoable step
+    pub fn reset(&mut self, data: &geometry::PuzzleData) -> bool {
+        let edges = self.connected_edges(data).collect();
+        self.change_edges(data, edges, vec![], Move::Reset)
+    }
+
+    // Removes every edge that isn't part of a revealed triangle, wrong or not, as a single undoable step
+    pub fn clear_mistakes(&mut self, data: &geometry::PuzzleData) -> bool {
+        let edges = self.connected_edge_ids()
+            .filter(|&id| !self.permanent_edges.contains(id))
+            .map(|id| data.get_edge(id))
+            .chain(self.wrong_edges())
+            .collect();
+        self.change_edges(data, edges, vec![], Move::ClearMistakes)
+    }
+
+    // Finishes the puzzle by removing every wrong edge and connecting every missing one as a single
+    // undoable step. Edges connected this way never count as mistakes.
+    pub fn reveal_all(&mut self, data: &geometry::PuzzleData) -> bool {
+        let wrong = self.wrong_edges().collect();
+        let missing = (0..data.num_edges() as u32)
+            .filter(|&id| !self.connected_edges.contains(id))
+            .map(|id| data.get_edge(id))
+            .collect();
+        self.change_edges(data, wrong, missing, Move::RevealAll)
+    }
+
+    // Applies removals and then additions as one history step, logging `action` if anything changed
+    fn change_edges(
+        &mut self,
+        data: &geometry::PuzzleData,
+        remove: Vec<(u32, u32)>,
+        connect: Vec<(u32, u32)>,
+        action: Move,
+    ) -> bool {
+        let mut step = vec![];
+        for edge in remove {
+            if self.remove_edge(data, edge) { step.push(EdgeChange::Disconnected(edge)); }
+        }
+        for edge in connect {
+            if self.add_edge(data, edge) { step.push(EdgeChange::Connected(edge)); }
+        }
+
+        let changed = !step.is_empty();
+        self.history.record(step);
+        if changed { self.log_move(action); }
+        changed
+    }
+
+    // Returns whether there was anything to undo
+    pub fn undo(&mut self, data: &geometry::PuzzleData) -> bool {
+        match self.history.take_undo() {
+            Some(step) => {
+                for change in step.iter().rev() { self.apply(data, change.inverse()); }
+                self.history.push_redo(step);
+                self.log_move(Move::Undo);
+                true
+            },
+            None => false,
+        }
+    }
+
+    // Returns whether there was anything to redo
+    pub fn redo(&mut self, data: &geometry::PuzzleData) -> bool {
+        match self.history.take_redo() {
+            Some(step) => {
+                for change in &step { self.apply(data, *change); }
+                self.history.push_undo(step);
+                self.log_move(Move::Redo);
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn apply(&mut self, data: &geometry::PuzzleData, change: EdgeChange) -> bool {
+        match change {
+            EdgeChange::Connected(edge) => self.add_edge(data, edge),
+            EdgeChange::Disconnected(edge) => self.remove_edge(data, edge),
+        }
+    }
+
+    // Connects an edge without checking rules or recording history, unlocking any triangles it completes
+    fn add_edge(&mut self, data: &geometry::PuzzleData, edge_ordered: (u32, u32)) -> bool {
+        let id = match data.get_edge_id(&edge_ordered) {
+            Some(id) => id,
+            None => {
+                if !self.mistake_edges.insert(edge_ordered) { return false }
+                self.count_edge(edge_ordered, 1, true);
+                return true
+            },
+        };
+        if !self.connected_edges.insert(id) { return false }
+        self.count_edge(edge_ordered, 1, false);
+
+        for &triangle in data.triangles_with_edge(id) {
+            let reqs = &mut self.triangle_reqs[triangle as usize];
+            *reqs -= 1;
+            if *reqs != 0 { continue }
+
+            self.unlocked_triangles.insert(triangle);
+            self.events.push(GameEvent::TriangleUnlocked { triangle: triangle as usize });
+            for e_perm in data.get_edges_for_triangle(triangle) {
+                if !self.permanent_edges.insert(e_perm) { continue }
+                for v in ends(data.get_edge(e_perm)) {
+                    self.permanent_by_vertex[v as usize] += 1;
+                    let complete = self.permanent_by_vertex[v as usize] as usize == data.num_edges_from_vertex(v);
+                    if complete && self.permanent_vertices.insert(v) {
+                        self.events.push(GameEvent::VertexCompleted { vertex: v });
                     }
                 }
             }
         }
+        if self.is_finished() {
+            self.events.push(GameEvent::PuzzleFinished { mistakes: self.total_mistakes });
+        }
+
+        true
     }
 
-    pub fn disconnect_edge(&mut self, data: &geometry::PuzzleData, edge: &(u32, u32)) {
-        let edge_ordered = if edge.0 > edge.1 { (edge.1, edge.0) } else { *edge };
-        if !self.connected_edges.remove(&edge_ordered) { return }
-        self.connected_edges_by_vertex.entry(edge.0).and_modify(|e| { e.remove(&edge_ordered); });
-        self.connected_edges_by_vertex.entry(edge.1).and_modify(|e| { e.remove(&edge_ordered); });
-
-        if let Some(triangles_with_edge) = data.triangles_with_edge(&edge_ordered) {
-            for &triangle in triangles_with_edge {
-                if self.triangle_reqs[triangle] == 0 { self.unlocked_triangles.remove(&triangle); }
-                self.triangle_reqs[triangle] += 1;
+    // Disconnects an edge without recording history. Players can only remove non-permanent edges,
+    // but undo can take away the last edge of an unlocked triangle, which locks it again.
+    fn remove_edge(&mut self, data: &geometry::PuzzleData, edge_ordered: (u32, u32)) -> bool {
+        let id = match data.get_edge_id(&edge_ordered) {
+            Some(id) => id,
+            None => {
+                if !self.mistake_edges.remove(&edge_ordered) { return false }
+                self.count_edge(edge_ordered, -1, true);
+                return true
+            },
+        };
+        if !self.connected_edges.remove(id) { return false }
+        self.count_edge(edge_ordered, -1, false);
+
+        for &triangle in data.triangles_with_edge(id) {
+            if self.triangle_reqs[triangle as usize] == 0 {
+                self.unlocked_triangles.remove(triangle);
+                self.events.push(GameEvent::TriangleRelocked { triangle: triangle as usize });
+                self.relock_triangle(data, triangle);
             }
+            self.triangle_reqs[triangle as usize] += 1;
         }
+
+        true
     }
 
-    pub fn disconnect_from_vertex(&mut self, data: &geometry::PuzzleData, vertex: u32) {
-        if self.is_permanent_and_complete(vertex) { return }
-        if let Some(edges) = self.connected_edges_by_vertex.get(&vertex) {
-            for edge in edges.clone() {
-                if self.permanent_edges.contains(&edge) { continue }
-                self.disconnect_edge(data, &edge);
+    // Edges stay permanent as long as any unlocked triangle still uses them
+    fn relock_triangle(&mut self, data: &geometry::PuzzleData, triangle: u32) {
+        for e_perm in data.get_edges_for_triangle(triangle) {
+            let still_unlocked = data.triangles_with_edge(e_perm).iter().any(|&t| self.unlocked_triangles.contains(t));
+            if still_unlocked || !self.permanent_edges.remove(e_perm) { continue }
+
+            for v in ends(data.get_edge(e_perm)) {
+                self.permanent_by_vertex[v as usize] -= 1;
+                self.permanent_vertices.remove(v);
             }
         }
     }
 
-    fn is_permanent_and_complete(&self, vertex: u32) -> bool {
-        let num_permanent = self.get_permanent_edges_for_vertex(vertex);
-        let num_connected = self.connected_edges_by_vertex[&vertex].len();
-        self.permanent_vertices.contains(&vertex) && num_permanent == num_connected
+    // Keeps the per-vertex counts of connected and wrong edges in step with the edge sets
+    fn count_edge(&mut self, edge: (u32, u32), delta: i32, wrong: bool) {
+        for v in ends(edge) {
+            let connected = &mut self.connected_by_vertex[v as usize];
+            *connected = (*connected as i32 + delta) as u32;
+            if wrong {
+                let mistakes = &mut self.mistakes_by_vertex[v as usize];
+                *mistakes = (*mistakes as i32 + delta) as u32;
+            }
+        }
     }
 
+    // A useful next step for a stuck player, or None if the puzzle is finished
+    pub fn hint(&self, data: &geometry::PuzzleData) -> Option<Hint> { hint::suggest(data, self) }
+
+    pub fn get_total_mistakes(&self) -> usize { self.total_mistakes }
     pub fn is_finished(&self) -> bool { self.unlocked_triangles.len() == self.triangle_reqs.len() }
-    pub fn get_connected_edges(&self) -> &HashSet<(u32, u32)> { &self.connected_edges }
-    pub fn get_unlocked_triangles(&self) -> &HashSet<usize> { &self.unlocked_triangles }
-    pub fn get_permanent_edges_for_vertex(&self, vertex: u32) -> usize {
-        self.permanent_edges_by_vertex.get(&vertex).map(|e| e.len()).unwrap_or(0)
+
+    // Every connected edge with its vertices sorted, puzzle edges first and then wrong ones
+    pub fn connected_edges<'a>(&'a self, data: &'a geometry::PuzzleData) -> impl Iterator<Item = (u32, u32)> + 'a {
+        self.connected_edge_ids().map(move |id| data.get_edge(id)).chain(self.wrong_edges())
+    }
+
+    pub fn num_connected_edges(&self) -> usize { self.connected_edges.len() + self.mistake_edges.len() }
+    pub fn connected_edge_ids(&self) -> impl Iterator<Item = u32> + '_ { self.connected_edges.iter() }
+    pub fn is_edge_connected(&self, id: u32) -> bool { self.connected_edges.contains(id) }
+    pub fn is_wrong_edge(&self, edge: &(u32, u32)) -> bool { self.mistake_edges.contains(edge) }
+    pub fn wrong_edges(&self) -> impl Iterator<Item = (u32, u32)> + '_ { self.mistake_edges.iter().cloned() }
+    pub fn get_wrong_edges(&self) -> usize { self.mistake_edges.len() }
+    pub fn unlocked_triangles(&self) -> impl Iterator<Item = u32> + '_ { self.unlocked_triangles.iter() }
+    pub fn num_unlocked_triangles(&self) -> usize { self.unlocked_triangles.len() }
+    pub fn vertex_status(&self, data: &geometry::PuzzleData, vertex: u32) -> VertexStatus {
+        let count = |counts: &Vec<u32>| counts.get(vertex as usize).cloned().unwrap_or(0) as usize;
+        VertexStatus {
+            required: data.num_edges_from_vertex(vertex),
+            connected: count(&self.connected_by_vertex),
+            permanent: count(&self.permanent_by_vertex),
+            surplus: count(&self.mistakes_by_vertex),
+        }
+    }
+}
+
+// Vertices an edge touches
+fn ends(edge: (u32, u32)) -> [u32; 2] { [edge.0, edge.1] }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square() -> geometry::PuzzleData {
+        geometry::PuzzleData::parse(include_str!("../../puzzles/1.txt")).unwrap()
+    }
+
+    #[test]
+    fn undo_and_redo_restore_exact_state() {
+        let data = square();
+        let mut state = PuzzleState::from_data(&data, Rules { edge_mode: EdgeMode::Relaxed });
+        for edge in &[(0, 1), (1, 2), (0, 2)] { state.connect_edge(&data, edge); }
+        assert_eq!(state.num_unlocked_triangles(), 1);
+        assert_eq!(state.vertex_status(&data, 1).permanent, 2);
+
+        assert!(state.undo(&data));
+        assert_eq!(state.num_unlocked_triangles(), 0);
+        assert_eq!(state.vertex_status(&data, 1).permanent, 0);
+        assert_eq!(state.num_connected_edges(), 2);
+
+        assert!(state.redo(&data));
+        assert_eq!(state.num_unlocked_triangles(), 1);
+        assert!(!state.redo(&data));
+    }
+
+    #[test]
+    fn disconnecting_a_vertex_is_one_step() {
+        let data = square();
+        let mut state = PuzzleState::from_data(&data, Rules { edge_mode: EdgeMode::Relaxed });
+        for edge in &[(0, 1), (0, 2), (0, 3)] { state.connect_edge(&data, edge); }
+        assert!(state.disconnect_from_vertex(&data, 0));
+        assert_eq!(state.num_connected_edges(), 0);
+
+        assert!(state.undo(&data));
+        assert_eq!(state.num_connected_edges(), 3);
+
+        // A new move clears anything that could have been redone
+        state.connect_edge(&data, &(1, 3));
+        assert!(!state.redo(&data));
+    }
+
+    #[test]
+    fn bulk_commands_are_single_undoable_steps() {
+        let data = square();
+        let mut state = PuzzleState::from_data(&data, Rules { edge_mode: EdgeMode::Relaxed });
+        for edge in &[(0, 1), (1, 2), (0, 2), (1, 3), (2, 3)] { state.connect_edge(&data, edge); }
+
+        // Only the edges of the revealed triangle stay
+        assert!(state.clear_mistakes(&data));
+        assert_eq!((state.num_connected_edges(), state.get_wrong_edges()), (3, 0));
+        assert!(!state.clear_mistakes(&data));
+        assert!(state.undo(&data));
+        assert_eq!(state.num_connected_edges(), 5);
+
+        assert!(state.reset(&data));
+        assert_eq!((state.num_connected_edges(), state.num_unlocked_triangles()), (0, 0));
+        assert!(state.undo(&data));
+
+        assert!(state.reveal_all(&data));
+        assert!(state.is_finished());
+        assert_eq!((state.num_connected_edges(), state.get_wrong_edges(), state.get_total_mistakes()), (5, 0, 1));
+
+        let replayed = PuzzleState::replay(&data, state.get_move_log()).unwrap();
+        assert!(replayed.connected_edges(&data).eq(state.connected_edges(&data)));
+        assert!(replayed.is_finished());
+    }
+
+    #[test]
+    fn replaying_a_move_log_reproduces_the_state() {
+        let data = square();
+        let mut state = PuzzleState::from_data(&data, Rules { edge_mode: EdgeMode::Relaxed });
+        state.set_clock(100.0);
+        assert!(state.connect_edge(&data, &(1, 0)));
+        state.set_clock(250.0);
+        assert!(state.connect_edge(&data, &(1, 3)));
+        state.set_clock(400.0);
+        assert!(state.connect_edge(&data, &(2, 1)));
+        state.set_clock(900.0);
+        assert!(state.undo(&data));
+        state.set_clock(1000.0);
+        assert!(state.disconnect_from_vertex(&data, 1));
+        state.set_clock(1200.0);
+        assert!(state.connect_edge(&data, &(0, 2)));
+
+        let log = MoveLog::from_json(&state.get_move_log().to_json()).unwrap();
+        assert_eq!(&log, state.get_move_log());
+        let vertex = data.canonical_vertex_id(1);
+        assert_eq!(log.moves[4], TimedMove { time: 1000.0, action: Move::DisconnectVertex { vertex } });
+
+        let replayed = PuzzleState::replay(&data, &log).unwrap();
+        assert!(replayed.connected_edges(&data).eq(state.connected_edges(&data)));
+        assert!(replayed.unlocked_triangles().eq(state.unlocked_triangles()));
+        assert_eq!(replayed.get_total_mistakes(), state.get_total_mistakes());
+        assert_eq!(replayed.get_move_log(), state.get_move_log());
+    }
+
+    #[test]
+    fn replayed_states_have_no_pending_events() {
+        let data = square();
+        let mut state = PuzzleState::from_data(&data, Rules { edge_mode: EdgeMode::Relaxed });
+        assert!(state.reveal_all(&data));
+        assert!(!state.drain_events().is_empty());
+
+        let mut replayed = PuzzleState::replay(&data, state.get_move_log()).unwrap();
+        assert!(replayed.is_finished());
+        assert!(replayed.drain_events().is_empty());
     }
-    pub fn get_non_permanent_edges_for_vertex(&self, vertex: u32) -> usize {
-        self.connected_edges_by_vertex.get(&vertex).map(|e| e.len()).unwrap_or(0) 
-            - self.get_permanent_edges_for_vertex(vertex)
+
+    #[test]
+    fn replay_rejects_moves_that_do_not_apply() {
+        let data = square();
+        let other = geometry::PuzzleData::parse(include_str!("../../puzzles/2.txt")).unwrap();
+        let log = |edge_mode: &str, moves: &str| MoveLog::from_json(&format!(
+            r#"{{"version":1,"puzzle":"{}","edge_mode":"{}","moves":[{}]}}"#, puzzle_id(data.fingerprint()), edge_mode, moves
+        ));
+
+        // Canonical ids 1 and 2 are opposite corners of the square
+        let diagonal = log("Strict", r#"{"time":0,"action":"connect","from":1,"to":2}"#).unwrap();
+        assert!(PuzzleState::replay(&data, &diagonal).is_err());
+        let out_of_range = log("Relaxed", r#"{"time":0,"action":"connect","from":1,"to":99}"#).unwrap();
+        assert!(PuzzleState::replay(&data, &out_of_range).is_err());
+        let valid = log("Relaxed", r#"{"time":0,"action":"connect","from":1,"to":2}"#).unwrap();
+        assert!(matches!(PuzzleState::replay(&other, &valid), Err(ReplayError::WrongPuzzle { .. })));
+        assert!(log("Relaxed", r#"{"time":5,"action":"undo"},{"time":1,"action":"redo"}"#).is_err());
+    }
+
+    #[test]
+    fn saved_progress_restores_edges_and_mistakes() {
+        let data = square();
+        let mut state = PuzzleState::from_data(&data, Rules { edge_mode: EdgeMode::Relaxed });
+        for edge in &[(0, 1), (1, 2), (2, 0), (1, 3)] { state.connect_edge(&data, edge); }
+        state.disconnect_from_vertex(&data, 3);
+        state.connect_edge(&data, &(3, 0));
+
+        let saved = SavedProgress::from_json(&state.save(&data).to_json()).unwrap();
+        // Canonical ids number the corners by position: 0 -> 0, 1 -> 2, 2 -> 3, 3 -> 1
+        assert_eq!(saved.edges, vec![(0, 1), (0, 2), (0, 3), (2, 3)]);
+        let restored = PuzzleState::from_save(&data, &saved).unwrap();
+        assert!(restored.connected_edges(&data).eq(state.connected_edges(&data)));
+        assert!(restored.unlocked_triangles().eq(state.unlocked_triangles()));
+        assert_eq!(restored.get_total_mistakes(), 1);
+    }
+
+    #[test]
+    fn move_logs_of_restored_progress_replay_to_the_same_state() {
+        let data = square();
+        let mut state = PuzzleState::from_data(&data, Rules { edge_mode: EdgeMode::Relaxed });
+        for edge in &[(0, 1), (1, 2), (1, 3)] { state.connect_edge(&data, edge); }
+
+        let mut restored = PuzzleState::from_save(&data, &state.save(&data)).unwrap();
+        restored.set_clock(500.0);
+        assert!(restored.connect_edge(&data, &(2, 0)));
+        assert!(restored.undo(&data));
+        assert!(restored.connect_edge(&data, &(0, 2)));
+
+        let log = MoveLog::from_json(&restored.get_move_log().to_json()).unwrap();
+        let replayed = PuzzleState::replay(&data, &log).unwrap();
+        assert!(replayed.connected_edges(&data).eq(restored.connected_edges(&data)));
+        assert!(replayed.unlocked_triangles().eq(restored.unlocked_triangles()));
+        assert_eq!(replayed.get_move_log(), restored.get_move_log());
+    }
+
+    #[test]
+    fn saves_only_load_against_their_own_puzzle() {
+        let data = square();
+        let other = geometry::PuzzleData::parse(include_str!("../../puzzles/2.txt")).unwrap();
+        let mut state = PuzzleState::from_data(&data, Rules { edge_mode: EdgeMode::Strict });
+        state.connect_edge(&data, &(0, 1));
+        let saved = state.save(&data);
+        assert!(matches!(PuzzleState::from_save(&other, &saved), Err(SaveError::WrongPuzzle { .. })));
+
+        let tampered = SavedProgress { edges: vec![(1, 2)], ..saved };
+        assert!(matches!(PuzzleState::from_save(&data, &tampered), Err(SaveError::InvalidEdge { .. })));
+    }
+
+    #[test]
+    fn progress_fingerprint_follows_connected_edges() {
+        let data = square();
+        let mut state = PuzzleState::from_data(&data, Rules { edge_mode: EdgeMode::Relaxed });
+        let empty = state.fingerprint(&data);
+        state.connect_edge(&data, &(0, 1));
+        let one_edge = state.fingerprint(&data);
+        assert_ne!(empty, one_edge);
+        state.undo(&data);
+        assert_eq!(state.fingerprint(&data), empty);
+        state.redo(&data);
+        assert_eq!(state.fingerprint(&data), one_edge);
+    }
+
+    #[test]
+    fn moves_emit_game_events() {
+        let data = square();
+        let mut state = PuzzleState::from_data(&data, Rules { edge_mode: EdgeMode::Strict });
+        assert!(!state.connect_edge(&data, &(3, 1)));
+        for edge in &[(0, 1), (1, 2), (0, 2)] { state.connect_edge(&data, edge); }
+        assert_eq!(state.drain_events(), vec![
+            GameEvent::EdgeRejected { from: 3, to: 1 },
+            GameEvent::TriangleUnlocked { triangle: 0 },
+            GameEvent::VertexCompleted { vertex: 1 },
+        ]);
+
+        state.undo(&data);
+        assert_eq!(state.drain_events(), vec![GameEvent::TriangleRelocked { triangle: 0 }]);
+
+        for edge in &[(0, 2), (2, 3), (0, 3)] { state.connect_edge(&data, edge); }
+        let events = state.drain_events();
+        // Both triangles unlock, the other three corners complete and then the puzzle is done
+        assert_eq!(events.len(), 7);
+        assert_eq!(events[6], GameEvent::PuzzleFinished { mistakes: 0 });
+        assert!(state.drain_events().is_empty());
     }
-    pub fn should_be_interactable(&self, data: &geometry::PuzzleData, vertex: u32) -> bool {
-        let not_done = data.num_edges_from_vertex(vertex) > self.get_permanent_edges_for_vertex(vertex);
-        let has_non_permanent = self.get_non_permanent_edges_for_vertex(vertex) > 0;
-        not_done || has_non_permanent
+
+    #[test]
+    fn hints_point_out_mistakes_then_complete_triangles() {
+        let data = square();
+        let mut state = PuzzleState::from_data(&data, Rules { edge_mode: EdgeMode::Relaxed });
+        state.connect_edge(&data, &(1, 3));
+        state.connect_edge(&data, &(0, 1));
+        state.connect_edge(&data, &(1, 2));
+        assert_eq!(state.hint(&data), Some(Hint::Remove((1, 3))));
+
+        state.disconnect_from_vertex(&data, 3);
+        assert_eq!(state.hint(&data), Some(Hint::Connect((0, 2))));
+
+        for edge in &[(0, 2), (2, 3), (0, 3)] { state.connect_edge(&data, edge); }
+        assert_eq!(state.hint(&data), None);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn vertex_status_handles_untouched_and_over_connected_vertices() {
+        let data = square();
+        let mut state = PuzzleState::from_data(&data, Rules { edge_mode: EdgeMode::Relaxed });
+        assert_eq!(state.vertex_status(&data, 1), VertexStatus { required: 2, connected: 0, permanent: 0, surplus: 0 });
+        assert!(!state.disconnect_from_vertex(&data, 1));
+
+        for edge in &[(0, 1), (1, 2), (0, 2), (1, 3)] { state.connect_edge(&data, edge); }
+        let status = state.vertex_status(&data, 1);
+        assert_eq!(status, VertexStatus { required: 2, connected: 3, permanent: 2, surplus: 1 });
+        assert_eq!((status.remaining(), status.removable()), (0, 1));
+        assert!(status.is_interactable() && status.is_over_connected());
+
+        assert!(state.disconnect_from_vertex(&data, 1));
+        assert!(state.vertex_status(&data, 1).is_done());
+        assert!(!state.disconnect_from_vertex(&data, 1));
+    }
+
+    #[test]
+    fn strict_mode_refuses_edges_outside_the_puzzle() {
+        let data = square();
+        let mut state = PuzzleState::from_data(&data, Rules { edge_mode: EdgeMode::Strict });
+        assert!(!state.connect_edge(&data, &(1, 3)));
+        assert_eq!(state.num_connected_edges(), 0);
+        assert!(state.connect_edge(&data, &(2, 0)));
+        assert_eq!(state.get_total_mistakes(), 0);
+    }
+
+    #[test]
+    fn relaxed_mode_tracks_edges_outside_the_puzzle_as_mistakes() {
+        let data = square();
+        let mut state = PuzzleState::from_data(&data, Rules { edge_mode: EdgeMode::Relaxed });
+        assert!(state.connect_edge(&data, &(3, 1)));
+        assert!(state.is_wrong_edge(&(1, 3)));
+        assert_eq!(state.vertex_status(&data, 3).surplus, 1);
+        assert_eq!(state.vertex_status(&data, 0).surplus, 0);
+        state.disconnect_from_vertex(&data, 1);
+        assert_eq!(state.get_wrong_edges(), 0);
+        assert_eq!(state.vertex_status(&data, 1).surplus, 0);
+        assert_eq!(state.get_total_mistakes(), 1);
+    }
+}
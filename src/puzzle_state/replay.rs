@@ -0,0 +1,78 @@
+use serde::{Deserialize, Serialize};
+use super::EdgeMode;
+
+const REPLAY_VERSION: u32 = 1;
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum ReplayError {
+        InvalidLog { reason: String } {
+            display("Invalid move log: {}", reason)
+        }
+        UnsupportedVersion { version: u32 } {
+            display("Unsupported move log version {}, expected {}", version, REPLAY_VERSION)
+        }
+        WrongPuzzle { expected: String, found: String } {
+            display("Move log is for puzzle {} but this is puzzle {}", found, expected)
+        }
+        IllegalMove { index: usize, reason: String } {
+            display("Move {} can't be replayed: {}", index, reason)
+        }
+    }
+}
+
+// A player action that changed the puzzle state. Actions that didn't change anything aren't logged.
+// Vertices are canonical ids, see PuzzleData::canonical_vertex_id.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Move {
+    Connect { from: u32, to: u32 },
+    DisconnectVertex { vertex: u32 },
+    Reset,
+    ClearMistakes,
+    RevealAll,
+    Undo,
+    Redo,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TimedMove {
+    pub time: f64, // milliseconds since the puzzle was started
+    #[serde(flatten)]
+    pub action: Move,
+}
+
+// Everything needed to rebuild a puzzle state from scratch, given the same puzzle:
+//
+//     {"version": 1, "puzzle": "5f0e7a3c1b9d2e48", "edge_mode": "Relaxed",
+//      "moves": [{"time": 1520.5, "action": "connect", "from": 0, "to": 3}, ...]}
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MoveLog {
+    pub version: u32,
+    pub puzzle: String, // fingerprint of the puzzle as 16 hex digits
+    pub edge_mode: EdgeMode,
+    pub moves: Vec<TimedMove>,
+}
+
+impl MoveLog {
+    pub fn new(puzzle: String, edge_mode: EdgeMode) -> MoveLog {
+        MoveLog { version: REPLAY_VERSION, puzzle, edge_mode, moves: vec![] }
+    }
+
+    pub fn from_json(json: &str) -> Result<MoveLog, ReplayError> {
+        let out: MoveLog = serde_json::from_str(json).map_err(|e| ReplayError::InvalidLog { reason: e.to_string() })?;
+        if out.version != REPLAY_VERSION { return Err(ReplayError::UnsupportedVersion { version: out.version }) }
+        let mut previous = 0.0;
+        for (idx, m) in out.moves.iter().enumerate() {
+            if !m.time.is_finite() || m.time < previous {
+                return Err(ReplayError::IllegalMove { index: idx, reason: "moves are out of order".to_string() });
+            }
+            previous = m.time;
+        }
+        Ok(out)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
@@ -0,0 +1,15 @@
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+// What happens when the player draws a line that isn't an edge of any triangle
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EdgeMode {
+    Strict, // the line is refused
+    Relaxed, // the line is drawn but counts as a mistake
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rules {
+    pub edge_mode: EdgeMode,
+}
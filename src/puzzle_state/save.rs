@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+use super::EdgeMode;
+
+const SAVE_VERSION: u32 = 1;
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum SaveError {
+        InvalidSave { reason: String } {
+            display("Invalid save: {}", reason)
+        }
+        UnsupportedVersion { version: u32 } {
+            display("Unsupported save version {}, expected {}", version, SAVE_VERSION)
+        }
+        WrongPuzzle { expected: String, found: String } {
+            display("Save is for puzzle {} but this is puzzle {}", found, expected)
+        }
+        InvalidEdge { edge: (u32, u32), reason: String } {
+            display("Saved edge {} {} can't be restored: {}", edge.0, edge.1, reason)
+        }
+    }
+}
+
+// Progress on a puzzle, just the connected edges since everything else follows from them:
+//
+//     {"version": 1, "puzzle": "5f0e7a3c1b9d2e48", "edge_mode": "Relaxed", "mistakes": 2, "edges": [[0, 1], [1, 2]]}
+//
+// Edges are between canonical vertex ids, so a save still loads if the puzzle file gets reordered.
+// Undo history and the move log aren't saved, so they start over when progress is restored.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SavedProgress {
+    pub version: u32,
+    pub puzzle: String, // fingerprint of the puzzle as 16 hex digits
+    pub edge_mode: EdgeMode,
+    pub mistakes: usize,
+    pub edges: Vec<(u32, u32)>, // sorted, with the lower vertex first
+}
+
+impl SavedProgress {
+    pub fn new(fingerprint: u64, edge_mode: EdgeMode, mistakes: usize, mut edges: Vec<(u32, u32)>) -> SavedProgress {
+        edges.sort();
+        SavedProgress { version: SAVE_VERSION, puzzle: puzzle_id(fingerprint), edge_mode, mistakes, edges }
+    }
+
+    pub fn from_json(json: &str) -> Result<SavedProgress, SaveError> {
+        let out: SavedProgress = serde_json::from_str(json).map_err(|e| SaveError::InvalidSave { reason: e.to_string() })?;
+        if out.version != SAVE_VERSION { return Err(SaveError::UnsupportedVersion { version: out.version }) }
+        Ok(out)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+pub fn puzzle_id(fingerprint: u64) -> String { format!("{:016x}", fingerprint) }
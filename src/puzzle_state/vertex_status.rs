@@ -0,0 +1,26 @@
+// Everything about one vertex's edges that rendering and hit-testing care about, counted in one
+// place so that none of the differences between counts can go negative
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct VertexStatus {
+    pub required: usize, // puzzle edges from the vertex
+    pub connected: usize, // connected edges, right or wrong
+    pub permanent: usize, // connected edges of unlocked triangles, which can't be removed
+    pub surplus: usize, // connected edges that aren't part of the puzzle
+}
+
+impl VertexStatus {
+    // Puzzle edges that still need to become permanent
+    pub fn remaining(&self) -> usize { self.required.saturating_sub(self.permanent) }
+
+    // Connected edges the player can still take away
+    pub fn removable(&self) -> usize { self.connected.saturating_sub(self.permanent) }
+
+    // Every edge of the vertex is permanent and nothing else is connected to it
+    pub fn is_done(&self) -> bool { self.remaining() == 0 && self.removable() == 0 }
+
+    // Done vertices are neither drawn nor selectable, everything else is both. Vertices with all
+    // their puzzle edges stay around while they have extra connections so those can be removed.
+    pub fn is_interactable(&self) -> bool { !self.is_done() }
+
+    pub fn is_over_connected(&self) -> bool { self.surplus > 0 }
+}
@@ -0,0 +1,106 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use super::geometry::{PuzzleData, VERTEX_SELECT_RADIUS};
+use super::puzzle_state::PuzzleState;
+
+// Why the remaining triangles of a puzzle can't be revealed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Obstacle {
+    // Clicking on `vertex` selects `selected` instead (or nothing), so `edge` can never be drawn
+    Unselectable { edge: (u32, u32), vertex: u32, selected: Option<u32> },
+}
+
+impl fmt::Display for Obstacle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Obstacle::Unselectable { edge, vertex, selected: Some(other) } => write!(
+                f, "edge {} {} can't be drawn because clicking vertex {} selects vertex {}", edge.0, edge.1, vertex, other
+            ),
+            Obstacle::Unselectable { edge, vertex, selected: None } => write!(
+                f, "edge {} {} can't be drawn because vertex {} can't be selected", edge.0, edge.1, vertex
+            ),
+        }
+    }
+}
+
+// Finds edges that finish the puzzle from `state` when connected in order, going through the same
+// vertex selection a player's clicks go through. Wrong edges already in `state` are left alone since
+// they don't stop the puzzle from finishing.
+//
+// Connecting an edge never makes another vertex harder to select (finished vertices stop being
+// selectable, which only frees up their neighbors), so drawing whatever can be drawn until nothing
+// changes either finishes the puzzle or proves it can't be finished.
+pub fn solve(data: &PuzzleData, state: &PuzzleState) -> Result<Vec<(u32, u32)>, Vec<Obstacle>> {
+    let mut state = state.clone();
+    let mut remaining: BTreeSet<(u32, u32)> = (0..data.num_edges() as u32)
+        .filter(|&id| !state.is_edge_connected(id))
+        .map(|id| data.get_edge(id))
+        .collect();
+
+    let mut out = vec![];
+    loop {
+        let drawable: Vec<(u32, u32)> = remaining.iter()
+            .filter(|edge| selects(data, &state, edge.0) == Some(edge.0) && selects(data, &state, edge.1) == Some(edge.1))
+            .cloned()
+            .collect();
+        if drawable.is_empty() { break }
+
+        for edge in drawable {
+            // An earlier edge this round may have finished one of the endpoints
+            if selects(data, &state, edge.0) != Some(edge.0) || selects(data, &state, edge.1) != Some(edge.1) { continue }
+            state.connect_edge(data, &edge);
+            remaining.remove(&edge);
+            out.push(edge);
+        }
+    }
+
+    if state.is_finished() { return Ok(out) }
+
+    let mut obstacles = vec![];
+    for &edge in &remaining {
+        for &vertex in &[edge.0, edge.1] {
+            let selected = selects(data, &state, vertex);
+            if selected != Some(vertex) {
+                obstacles.push(Obstacle::Unselectable { edge, vertex, selected });
+                break;
+            }
+        }
+    }
+    Err(obstacles)
+}
+
+// Which vertex a click right on top of `vertex` would pick
+fn selects(data: &PuzzleData, state: &PuzzleState, vertex: u32) -> Option<u32> {
+    data.get_vertex_near(state, data.get_vertex(vertex), VERTEX_SELECT_RADIUS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::puzzle_state::{EdgeMode, Rules};
+
+    #[test]
+    fn solves_bundled_puzzles_from_any_state() {
+        for puzzle in &[include_str!("../../puzzles/1.txt"), include_str!("../../puzzles/2.txt"), include_str!("../../puzzles/3.txt")] {
+            let data = PuzzleData::parse(puzzle).unwrap();
+            let mut state = PuzzleState::from_data(&data, Rules { edge_mode: EdgeMode::Relaxed });
+            state.connect_edge(&data, &(0, 1));
+
+            let moves = solve(&data, &state).unwrap();
+            for edge in &moves { assert!(state.connect_edge(&data, edge)); }
+            assert!(state.is_finished());
+        }
+    }
+
+    #[test]
+    fn reports_vertices_that_can_not_be_selected() {
+        // Vertex 3 sits on top of vertex 1, and clicking there picks vertex 1 until it's finished,
+        // which needs the edge to vertex 3
+        let data = PuzzleData::parse("0 0\n1 0\n0 1\n1 0\n1 1\n255 0 0\n0 1 2 0\n1 3 4 0").unwrap();
+        let state = PuzzleState::from_data(&data, Rules { edge_mode: EdgeMode::Strict });
+        assert_eq!(solve(&data, &state).unwrap_err(), vec![
+            Obstacle::Unselectable { edge: (1, 3), vertex: 3, selected: Some(1) },
+            Obstacle::Unselectable { edge: (3, 4), vertex: 3, selected: Some(1) },
+        ]);
+    }
+}
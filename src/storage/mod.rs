@@ -0,0 +1,22 @@
+use wasm_bindgen::prelude::*;
+
+// Progress is kept in localStorage under a key per puzzle, so saves for different puzzles never clash
+fn key(puzzle_id: &str) -> String { format!("vertex-progress-{}", puzzle_id) }
+
+// Storage can be missing or disabled (private browsing, file:// pages), which callers treat as no save
+fn local_storage() -> Result<web_sys::Storage, JsValue> {
+    let window = web_sys::window().ok_or("No global window exists")?;
+    Ok(window.local_storage()?.ok_or("localStorage is not available")?)
+}
+
+pub fn store(puzzle_id: &str, save: &str) -> Result<(), JsValue> {
+    local_storage()?.set_item(&key(puzzle_id), save)
+}
+
+pub fn load(puzzle_id: &str) -> Result<Option<String>, JsValue> {
+    local_storage()?.get_item(&key(puzzle_id))
+}
+
+pub fn clear(puzzle_id: &str) -> Result<(), JsValue> {
+    local_storage()?.remove_item(&key(puzzle_id))
+}